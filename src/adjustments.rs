@@ -0,0 +1,78 @@
+use crate::cli::Args;
+use crate::dates::{parse_server_time, Timestamp};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+pub const ADJUSTMENTS_FILE: &str = "adjustments.json";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAdjustment {
+    name: String,
+    points: i32,
+    reason: String,
+    date: String,
+}
+
+/// A bonus, or a penalty if negative, given by hand rather than for a boss.
+pub struct Adjustment {
+    pub name: String,
+    pub points: i32,
+    pub reason: String,
+    pub time: Timestamp,
+    pub line: Option<usize>,
+}
+
+/// Reads the adjustments that fall within the period being calculated. The file is optional, so
+/// there are no adjustments if it doesn't exist.
+pub fn read_adjustments(args: &Args) -> Result<Vec<Adjustment>, Vec<String>> {
+    if !Path::new(ADJUSTMENTS_FILE).exists() {
+        return Ok(Vec::new());
+    }
+
+    let input = File::open(ADJUSTMENTS_FILE).expect("Cannot open adjustments.json");
+
+    let entries: Vec<Value> = serde_json::from_reader(BufReader::new(input))
+        .map_err(|e| vec![format!("not a valid json list: {e}")])?;
+
+    let mut adjustments = Vec::<Adjustment>::new();
+    let mut errors = Vec::<String>::new();
+
+    for (i, entry) in entries.into_iter().enumerate() {
+        let raw = match serde_json::from_value::<RawAdjustment>(entry) {
+            Ok(raw) => raw,
+            Err(e) => {
+                errors.push(format!("adjustment {}: {e}", i + 1));
+                continue;
+            }
+        };
+
+        let Some(time) = parse_server_time(&raw.date) else {
+            errors.push(format!(
+                "adjustment {}: cannot read date `{}`",
+                i + 1,
+                raw.date
+            ));
+            continue;
+        };
+
+        if args.mode.includes(time) {
+            adjustments.push(Adjustment {
+                name: raw.name,
+                points: raw.points,
+                reason: raw.reason,
+                time,
+                line: None,
+            });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(adjustments)
+    } else {
+        Err(errors)
+    }
+}
@@ -2,23 +2,27 @@ use std::collections::HashSet;
 
 use textdistance::{self};
 
+use crate::normalise::normalise;
+
+const MAX_SPLITS: usize = 3;
+
 pub struct Autocorrecter {
     vocab: HashSet<String>,
 }
 
 impl Autocorrecter {
     pub fn new(words: Vec<String>) -> Self {
-        let vocab = HashSet::from_iter(words.iter().cloned());
+        let vocab = HashSet::from_iter(words.iter().map(|w| normalise(w)));
 
         Self { vocab }
     }
 
     pub fn add_word(&mut self, word: String) {
-        let _ = self.vocab.insert(word);
+        let _ = self.vocab.insert(normalise(&word));
     }
 
     pub fn correct(&self, input_word: &str) -> Vec<String> {
-        let input_word = input_word.to_lowercase();
+        let input_word = normalise(input_word);
 
         let mut similarities: Vec<(String, f64)> = self
             .vocab
@@ -31,4 +35,43 @@ impl Autocorrecter {
 
         similarities[..5].to_vec()
     }
+
+    // Ways of reading the word as several known names run together, fewest names first
+    pub fn segment(&self, input_word: &str) -> Vec<Vec<String>> {
+        let input_word = normalise(input_word);
+
+        let mut bounds: Vec<usize> = input_word.char_indices().map(|(i, _)| i).collect();
+        bounds.push(input_word.len());
+
+        // Splits of the rest of the word from each boundary, the end having a single empty one
+        let mut splits: Vec<Vec<Vec<String>>> = vec![Vec::new(); bounds.len()];
+        splits[bounds.len() - 1].push(Vec::new());
+
+        for start in (0..bounds.len() - 1).rev() {
+            let mut found = Vec::<Vec<String>>::new();
+
+            for end in start + 2..bounds.len() {
+                let piece = &input_word[bounds[start]..bounds[end]];
+                if !self.vocab.contains(piece) {
+                    continue;
+                }
+
+                for rest in &splits[end] {
+                    let mut split = vec![piece.to_string()];
+                    split.extend(rest.iter().cloned());
+                    found.push(split);
+                }
+            }
+
+            found.sort_by_key(Vec::len);
+            found.truncate(MAX_SPLITS);
+            splits[start] = found;
+        }
+
+        splits
+            .swap_remove(0)
+            .into_iter()
+            .filter(|split| split.len() > 1)
+            .collect()
+    }
 }
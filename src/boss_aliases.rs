@@ -0,0 +1,81 @@
+use crate::catalog::{catalog, Alias};
+use crate::normalise::normalise;
+use colored::*;
+use regex::Regex;
+
+enum Rule {
+    Token(String, String),
+    Regex(Regex, String),
+}
+
+impl Rule {
+    fn apply(&self, token: &str) -> Option<String> {
+        let replacement = match self {
+            Rule::Token(original, replacement) => (token == original).then(|| replacement.clone()),
+            Rule::Regex(regex, replacement) => regex
+                .is_match(token)
+                .then(|| regex.replace(token, replacement).into_owned()),
+        };
+
+        replacement.filter(|r| r != token)
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Rule::Token(original, replacement) => format!("{original} -> {replacement}"),
+            Rule::Regex(regex, replacement) => format!("/{regex}/ -> {replacement}"),
+        }
+    }
+}
+
+lazy_static! {
+    static ref RULES: Vec<Rule> = catalog()
+        .bosses
+        .iter()
+        .flat_map(|boss| {
+            boss.aliases.iter().map(|alias| match alias {
+                Alias::Token(token) => Rule::Token(token.clone(), boss.key.clone()),
+                Alias::Regex { regex, replacement } => {
+                    let Ok(compiled) = Regex::new(&format!("^(?:{regex})$")) else {
+                        panic!(
+                            "Invalid alias regex `{regex}` for {} in bosses.json",
+                            boss.key
+                        );
+                    };
+
+                    Rule::Regex(compiled, replacement.clone())
+                }
+            })
+        })
+        .collect();
+}
+
+/// Applies the first alias rule that matches the whole of `token`, leaving any modifiers
+/// attached to it untouched. Returns `None` if no rule matches.
+pub fn rewrite_boss(token: &str) -> Option<String> {
+    let (boss, modifiers) = token.split_at(token.find('(').unwrap_or(token.len()));
+
+    RULES
+        .iter()
+        .find_map(|rule| rule.apply(boss))
+        .map(|replacement| format!("{replacement}{modifiers}"))
+}
+
+pub fn warn_roster_collisions(names: &[String]) {
+    for name in names {
+        for word in name.split_whitespace() {
+            let word = normalise(word);
+
+            for rule in RULES.iter() {
+                if let Some(replacement) = rule.apply(&word) {
+                    println!(
+                        "{} boss alias `{}` would rename `{word}` in roster name {} to `{replacement}`",
+                        "Warning:".yellow().bold(),
+                        rule.describe(),
+                        name.bold()
+                    );
+                }
+            }
+        }
+    }
+}
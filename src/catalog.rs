@@ -0,0 +1,578 @@
+use crate::dates::{parse_server_time, Timestamp};
+use crate::migrate;
+use crate::normalise::normalise;
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+use serde::{de, de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{copy, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::{iter, mem};
+
+pub const CATALOG_FILE: &str = "bosses.json";
+
+pub const SCHEMA_VERSION: u32 = 5;
+
+#[derive(Debug)]
+pub struct Catalog {
+    pub version: u32,
+    pub bosses: Vec<Boss>,
+    pub modifiers: Vec<Modifier>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Boss {
+    pub key: String,
+    pub name: String,
+    pub category: String,
+    #[serde(default)]
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<Alias>,
+    pub points: Point,
+    #[serde(default)]
+    pub revisions: Vec<Revision>,
+    #[serde(default)]
+    pub prio: bool,
+}
+
+// Points that replace a boss's original points from a given time onwards
+#[derive(Debug)]
+pub struct Revision {
+    pub from: Timestamp,
+    pub points: Point,
+}
+
+impl<'de> Deserialize<'de> for Revision {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct RawRevision {
+            from: String,
+            points: Point,
+        }
+
+        let raw = RawRevision::deserialize(deserializer)?;
+
+        let from = parse_server_time(&raw.from)
+            .ok_or_else(|| de::Error::custom(format!("cannot read date `{}`", raw.from)))?;
+
+        Ok(Revision {
+            from,
+            points: raw.points,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Alias {
+    Token(String),
+    Regex { regex: String, replacement: String },
+}
+
+#[derive(Debug)]
+pub enum Point {
+    Value(i32),
+    PerCount(PerCount),
+    Tiered(Vec<Tier>),
+}
+
+// Points for each of several drops at once, e.g. "rings3x6" is three 6 star rings
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PerCount {
+    pub count: CountRange,
+    pub values: BTreeMap<String, i32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CountRange {
+    pub min: i32,
+    pub max: i32,
+}
+
+// Points for each star of a boss at or above `level`, up to the next tier
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Tier {
+    pub level: i32,
+    pub points: BTreeMap<String, i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Modifier {
+    pub key: String,
+    #[serde(default = "no_multiplier")]
+    pub multiplier: f64,
+    #[serde(default)]
+    pub bonus: i32,
+    #[serde(default)]
+    pub rounding: Rounding,
+    #[serde(default)]
+    pub order: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub only: Option<Restriction>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<String>,
+}
+
+fn no_multiplier() -> f64 {
+    1.0
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Rounding {
+    Up,
+    Down,
+    #[default]
+    Nearest,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Restriction {
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub prio: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bosses: Vec<String>,
+}
+
+impl Boss {
+    /// The points in force at `time`, or the latest points if the time isn't known.
+    pub fn points_at(&self, time: Option<Timestamp>) -> &Point {
+        let revision = match time {
+            Some(time) => self.revisions.iter().rev().find(|r| r.from <= time),
+            None => self.revisions.last(),
+        };
+
+        revision.map_or(&self.points, |r| &r.points)
+    }
+
+    fn all_points(&self) -> impl Iterator<Item = &Point> {
+        iter::once(&self.points).chain(self.revisions.iter().map(|r| &r.points))
+    }
+}
+
+impl Modifier {
+    pub fn applies_to(&self, boss: &Boss) -> bool {
+        let Some(only) = &self.only else {
+            return true;
+        };
+
+        (!only.prio || boss.prio)
+            && (only.categories.is_empty() || only.categories.contains(&boss.category))
+            && (only.bosses.is_empty() || only.bosses.contains(&boss.key))
+    }
+
+    pub fn conflicts_with(&self, other: &Modifier) -> bool {
+        self.conflicts.contains(&other.key) || other.conflicts.contains(&self.key)
+    }
+
+    pub fn apply(&self, points: i32) -> i32 {
+        let points = f64::from(points) * self.multiplier + f64::from(self.bonus);
+
+        let points = match self.rounding {
+            Rounding::Up => points.ceil(),
+            Rounding::Down => points.floor(),
+            Rounding::Nearest => points.round(),
+        };
+
+        points as i32
+    }
+}
+
+impl Catalog {
+    pub fn get(&self, key: &str) -> Option<&Boss> {
+        self.bosses.iter().find(|b| b.key == key)
+    }
+
+    pub fn modifier(&self, key: &str) -> Option<&Modifier> {
+        self.modifiers.iter().find(|m| m.key == key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.bosses.iter().map(|b| &b.key)
+    }
+}
+
+/// Turns a token pattern into a regex matching the whole token. A pattern is either a regex
+/// between slashes, e.g. "/root\d*/", or text where `*` matches anything, `?` matches a single
+/// character and each `{name}` captures part of the token, e.g. "legacy{level}.{star}".
+pub fn pattern_regex(pattern: &str) -> Result<Regex, String> {
+    static PLACEHOLDER_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"\{(\w*)\}|\*|\?").expect("Invalid placeholder regex"));
+
+    if let Some(regex) = pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+        return Regex::new(&format!("^(?:{regex})$"))
+            .map_err(|e| format!("invalid pattern `{pattern}`: {e}"));
+    }
+
+    let mut regex = String::from("^");
+    let mut end = 0;
+
+    for caps in PLACEHOLDER_RE.captures_iter(pattern) {
+        let placeholder = caps.get(0).unwrap();
+
+        regex.push_str(&regex::escape(&pattern[end..placeholder.start()]));
+        end = placeholder.end();
+
+        match placeholder.as_str() {
+            "*" => regex.push_str(".*"),
+            "?" => regex.push('.'),
+            _ if caps[1].is_empty() => {
+                return Err(format!("pattern `{pattern}` has an unnamed placeholder"))
+            }
+            _ => regex.push_str(&format!("(?<{}>.+?)", &caps[1])),
+        }
+    }
+
+    regex.push_str(&regex::escape(&pattern[end..]));
+    regex.push('$');
+
+    Regex::new(&regex).map_err(|e| format!("invalid pattern `{pattern}`: {e}"))
+}
+
+fn parse_point(value: &Value) -> Result<Point, String> {
+    match value {
+        Value::Number(n) => match n.as_i64().and_then(|n| i32::try_from(n).ok()) {
+            Some(n) => Ok(Point::Value(n)),
+            None => Err(format!("points must be a whole number, found {n}")),
+        },
+        Value::Object(_) => serde_json::from_value::<PerCount>(value.clone())
+            .map(Point::PerCount)
+            .map_err(|e| e.to_string()),
+        Value::Array(tiers) => tiers
+            .iter()
+            .enumerate()
+            .map(|(i, tier)| {
+                serde_json::from_value::<Tier>(tier.clone())
+                    .map_err(|e| format!("tier {}: {e}", i + 1))
+            })
+            .collect::<Result<_, _>>()
+            .map(Point::Tiered),
+        _ => Err(format!(
+            "points must be a number, a count with values, or a list of tiers, found {value}"
+        )),
+    }
+}
+
+impl<'de> Deserialize<'de> for Point {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        parse_point(&Value::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+fn parse_entries<T: DeserializeOwned>(
+    raw: &Value,
+    section: &str,
+    label: &str,
+    errors: &mut Vec<String>,
+) -> Vec<T> {
+    let Some(entries) = raw.get(section).and_then(Value::as_array) else {
+        errors.push(format!("missing list of `{section}`"));
+        return Vec::new();
+    };
+
+    let mut parsed = Vec::<T>::new();
+
+    for (i, entry) in entries.iter().enumerate() {
+        match serde_json::from_value::<T>(entry.clone()) {
+            Ok(entry) => parsed.push(entry),
+            Err(e) => match entry.get("key").and_then(Value::as_str) {
+                Some(key) => errors.push(format!("{label} `{key}`: {e}")),
+                None => errors.push(format!("{label} {}: {e}", i + 1)),
+            },
+        }
+    }
+
+    parsed
+}
+
+fn write_raw(raw: &Value) {
+    let output = File::create(CATALOG_FILE).expect("Cannot write bosses.json");
+    serde_json::to_writer_pretty(BufWriter::new(output), raw).expect("Cannot write bosses.json");
+}
+
+fn read_catalog() -> Result<Catalog, Vec<String>> {
+    let catalog_input = File::open(CATALOG_FILE).expect("Cannot find bosses.json");
+
+    let mut raw: Value = serde_json::from_reader(BufReader::new(catalog_input))
+        .map_err(|e| vec![format!("not valid json: {e}")])?;
+
+    match raw.get("version").and_then(Value::as_u64) {
+        Some(version) if version == u64::from(SCHEMA_VERSION) => (),
+        Some(version) if version < u64::from(SCHEMA_VERSION) => {
+            migrate::upgrade(&mut raw, version).map_err(|e| vec![e])?;
+
+            let backup = format!("bosses-v{version}.json");
+            copy(CATALOG_FILE, &backup).expect("Cannot back up bosses.json");
+            write_raw(&raw);
+
+            println!(
+                "Upgraded {CATALOG_FILE} from version {version} to {SCHEMA_VERSION}. \
+                The old file was kept as {backup}."
+            );
+        }
+        Some(version) => {
+            return Err(vec![format!(
+                "version {version} is not supported, expected version {SCHEMA_VERSION}"
+            )])
+        }
+        None => return Err(vec!["missing `version`".to_string()]),
+    }
+
+    parse_catalog(&raw)
+}
+
+fn parse_catalog(raw: &Value) -> Result<Catalog, Vec<String>> {
+    let mut errors = Vec::<String>::new();
+
+    let bosses = parse_entries(raw, "bosses", "boss", &mut errors);
+    let modifiers = parse_entries(raw, "modifiers", "modifier", &mut errors);
+
+    if errors.is_empty() {
+        Ok(Catalog {
+            version: SCHEMA_VERSION,
+            bosses,
+            modifiers,
+        })
+    } else {
+        Err(errors)
+    }
+}
+
+fn validate_per_count(per_count: &PerCount, captures: &[String]) -> Vec<String> {
+    let mut errors = Vec::<String>::new();
+
+    for capture in ["count", "quality"] {
+        if !captures.iter().any(|c| c == capture) {
+            errors.push(format!(
+                "points per count need a `pattern` with a `{{{capture}}}` placeholder"
+            ));
+        }
+    }
+
+    let CountRange { min, max } = per_count.count;
+    if min < 1 || min > max {
+        errors.push(format!(
+            "count must go from 1 or more up to at least the minimum, found {min} to {max}"
+        ));
+    }
+
+    if per_count.values.is_empty() {
+        errors.push("points per count need at least one value".to_string());
+    }
+
+    for (quality, value) in per_count.values.iter() {
+        if value.checked_mul(max).is_none() {
+            errors.push(format!(
+                "points for `{quality}` are too large to multiply by a count of {max}"
+            ));
+        }
+    }
+
+    errors
+}
+
+fn validate_tiers(tiers: &[Tier], captures: &[String]) -> Vec<String> {
+    let mut errors = Vec::<String>::new();
+
+    for capture in ["level", "star"] {
+        if !captures.iter().any(|c| c == capture) {
+            errors.push(format!(
+                "tiered points need a `pattern` with a `{{{capture}}}` placeholder"
+            ));
+        }
+    }
+
+    for pair in tiers.windows(2) {
+        if pair[0].level <= pair[1].level {
+            errors.push(format!(
+                "tiers must go from highest level to lowest without repeats, \
+                but level {} comes before level {}",
+                pair[0].level, pair[1].level
+            ));
+        }
+    }
+
+    if let Some((first, rest)) = tiers.split_first() {
+        for tier in rest {
+            if !tier.points.keys().eq(first.points.keys()) {
+                errors.push(format!(
+                    "tier for level {} has different stars to the tier for level {}",
+                    tier.level, first.level
+                ));
+            }
+        }
+    }
+
+    errors
+}
+
+// Timer tokens are normalised before they are looked up, so anything written otherwise never matches
+fn check_normalised(what: &str, text: &str) -> Option<String> {
+    let normalised = normalise(text);
+
+    (normalised != text).then(|| format!("{what} `{text}` must be written as `{normalised}`"))
+}
+
+fn validate(catalog: &Catalog) -> Vec<String> {
+    let mut errors = Vec::<String>::new();
+    let mut seen = HashSet::<&str>::new();
+
+    for boss in catalog.bosses.iter() {
+        let key = &boss.key;
+
+        if !seen.insert(key) {
+            errors.push(format!("boss `{key}`: listed more than once"));
+        }
+
+        if catalog.modifier(key).is_some() {
+            errors.push(format!("boss `{key}`: has the same name as a modifier"));
+        }
+
+        errors.extend(check_normalised("key", key).map(|e| format!("boss `{key}`: {e}")));
+
+        for alias in boss.aliases.iter() {
+            let unnormalised = match alias {
+                Alias::Token(token) => check_normalised("alias", token),
+                Alias::Regex { regex, replacement } => {
+                    if let Err(e) = Regex::new(regex) {
+                        errors.push(format!("boss `{key}`: invalid alias regex `{regex}`: {e}"));
+                    }
+
+                    check_normalised("alias replacement", replacement)
+                }
+            };
+
+            errors.extend(unnormalised.map(|e| format!("boss `{key}`: {e}")));
+        }
+
+        for pair in boss.revisions.windows(2) {
+            if pair[0].from >= pair[1].from {
+                errors.push(format!(
+                    "boss `{key}`: revisions must be in date order without repeats, \
+                    but {} comes before {}",
+                    pair[0].from, pair[1].from
+                ));
+            }
+        }
+
+        for revision in boss.revisions.iter() {
+            if mem::discriminant(&revision.points) != mem::discriminant(&boss.points) {
+                errors.push(format!(
+                    "boss `{key}`: revision from {} has a different kind of points to the original",
+                    revision.from
+                ));
+            }
+        }
+
+        let captures: Vec<String> = match boss.pattern.as_deref().map(pattern_regex) {
+            Some(Ok(regex)) => regex.capture_names().flatten().map(String::from).collect(),
+            Some(Err(e)) => {
+                errors.push(format!("boss `{key}`: {e}"));
+                continue;
+            }
+            None => Vec::new(),
+        };
+
+        for points in boss.all_points() {
+            let problems = match points {
+                Point::Value(_) => Vec::new(),
+                Point::PerCount(per_count) => validate_per_count(per_count, &captures),
+                Point::Tiered(tiers) => validate_tiers(tiers, &captures),
+            };
+
+            errors.extend(problems.into_iter().map(|e| format!("boss `{key}`: {e}")));
+        }
+    }
+
+    let mut seen = HashSet::<&str>::new();
+
+    for modifier in catalog.modifiers.iter() {
+        let key = &modifier.key;
+
+        if !seen.insert(key) {
+            errors.push(format!("modifier `{key}`: listed more than once"));
+        }
+
+        errors.extend(check_normalised("key", key).map(|e| format!("modifier `{key}`: {e}")));
+
+        if !modifier.multiplier.is_finite() || modifier.multiplier < 0.0 {
+            errors.push(format!(
+                "modifier `{key}`: multiplier must be zero or more, found {}",
+                modifier.multiplier
+            ));
+        }
+
+        for conflict in modifier.conflicts.iter() {
+            if catalog.modifier(conflict).is_none() {
+                errors.push(format!(
+                    "modifier `{key}`: conflicts with unknown modifier `{conflict}`"
+                ));
+            }
+        }
+
+        let Some(only) = &modifier.only else {
+            continue;
+        };
+
+        for boss in only.bosses.iter() {
+            if catalog.get(boss).is_none() {
+                errors.push(format!("modifier `{key}`: unknown boss `{boss}`"));
+            }
+        }
+
+        for category in only.categories.iter() {
+            if !catalog.bosses.iter().any(|b| &b.category == category) {
+                errors.push(format!("modifier `{key}`: unknown category `{category}`"));
+            }
+        }
+    }
+
+    errors
+}
+
+/// Loads bosses.json, creating it from the older config files if it doesn't exist yet, and
+/// checks it before any timers are read. Returns every problem found.
+pub fn init() -> Result<(), Vec<String>> {
+    let catalog = if Path::new(CATALOG_FILE).exists() {
+        read_catalog()?
+    } else {
+        let mut raw = migrate::from_legacy_files();
+        migrate::upgrade(&mut raw, 1).map_err(|e| vec![e])?;
+        write_raw(&raw);
+
+        println!(
+            "Created {CATALOG_FILE} from points.json, boss_aliases.json and prios.json. \
+            Those files are no longer read and can be deleted."
+        );
+
+        parse_catalog(&raw)?
+    };
+
+    let errors = validate(&catalog);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    CATALOG
+        .set(catalog)
+        .expect("bosses.json has already been loaded");
+
+    Ok(())
+}
+
+static CATALOG: OnceCell<Catalog> = OnceCell::new();
+
+pub fn catalog() -> &'static Catalog {
+    CATALOG.get().expect("bosses.json has not been loaded")
+}
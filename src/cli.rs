@@ -0,0 +1,181 @@
+use crate::config::CONFIG;
+use crate::dates::{parse_argument, to_server_time, week_containing, Timestamp};
+use chrono::{Days, NaiveDate, NaiveTime};
+use colored::*;
+use std::env;
+use std::process;
+
+pub enum Mode {
+    All,
+    Batch,
+    Range(Option<Timestamp>, Timestamp),
+}
+
+impl Mode {
+    /// Whether `time` falls within the period being calculated.
+    pub fn includes(&self, time: Timestamp) -> bool {
+        match self {
+            Mode::All | Mode::Batch => true,
+            Mode::Range(start, end) => !start.is_some_and(|start| time < start) && time < *end,
+        }
+    }
+}
+
+pub struct Args {
+    pub mode: Mode,
+    pub reference_date: Option<NaiveDate>,
+    pub dry_run_aliases: bool,
+    pub explain: bool,
+    pub member: Option<String>,
+    pub all_balances: bool,
+    pub fix: bool,
+    pub edit: bool,
+}
+
+fn usage_string() -> String {
+    let program_name = match env::consts::OS {
+        "windows" => r".\dkp-automator.exe",
+        _ => r"./dkp-automator",
+    };
+
+    format!(
+        "Usage:
+
+{}       Calculates dkp for all lines in timers.txt
+{}     Calculates dkp for each week in timers.txt separately, along with a summary
+{}    Calculates dkp for the week, as set in config.json, containing the date given
+                        Dates can be in any of the timestamp formats in config.json, e.g. \"2 Jun 2024\"
+                        You can also include a 24-hour time, e.g. \"2 Jun 2024 19:00\"
+
+Options:
+{}        Start of the period to calculate dkp for, in the server timezone from config.json
+{}          End of the period, defaults to 7 days after the start
+{}   Date used to resolve \"Today at\", \"Yesterday at\" and \"Last Monday at\"
+                        Defaults to the date timers.txt was last modified
+{}       Lists every boss rewritten by an alias in bosses.json without calculating dkp
+{}               Writes explain.txt, listing every line that gave or took points from each member
+{}       Prints the lines that gave or took points from one member
+{}          Keeps members whose total is zero or negative in the output
+{}                   Proposes fixes for common mistakes in invalid lines of timers.txt
+{}                  Walks through each invalid line of timers.txt to edit, ignore or skip it",
+        format!("{program_name} all").bold(),
+        format!("{program_name} batch").bold(),
+        format!("{program_name} <date>").bold(),
+        "--from <date>".bold(),
+        "--to <date>".bold(),
+        "--reference <date>".bold(),
+        "--dry-run-aliases".bold(),
+        "--explain".bold(),
+        "--member <name>".bold(),
+        "--all-balances".bold(),
+        "--fix".bold(),
+        "--edit".bold(),
+    )
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    println!(
+        "
+{message}
+
+{}
+",
+        usage_string()
+    );
+    process::exit(1);
+}
+
+fn server_time(date: NaiveDate, time: NaiveTime) -> Timestamp {
+    match to_server_time(date.and_time(time), CONFIG.timezone) {
+        Some(timestamp) => timestamp,
+        None => exit_with_usage(&format!("Date `{date}` is out of range.")),
+    }
+}
+
+fn parse_date(flag: &str, value: Option<String>) -> (NaiveDate, Option<NaiveTime>) {
+    let Some(value) = value else {
+        exit_with_usage(&format!("Missing date after `{flag}`."));
+    };
+
+    match parse_argument(&value) {
+        Some(date) => date,
+        None => exit_with_usage(&format!("Invalid date `{value}` for `{flag}`.")),
+    }
+}
+
+pub fn parse_args() -> Args {
+    let mut all = false;
+    let mut batch = false;
+    let mut week = None;
+    let mut from = None;
+    let mut to = None;
+    let mut reference_date = None;
+    let mut dry_run_aliases = false;
+    let mut explain = false;
+    let mut member = None;
+    let mut all_balances = false;
+    let mut fix = false;
+    let mut edit = false;
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--reference" => reference_date = Some(parse_date(&arg, args.next()).0),
+            "--from" => from = Some(parse_date(&arg, args.next())),
+            "--to" => to = Some(parse_date(&arg, args.next())),
+            "--dry-run-aliases" => dry_run_aliases = true,
+            "--explain" => explain = true,
+            "--all-balances" => all_balances = true,
+            "--fix" => fix = true,
+            "--edit" => edit = true,
+            "--member" => match args.next() {
+                Some(name) => member = Some(name),
+                None => exit_with_usage("Missing name after `--member`."),
+            },
+            _ if all || batch || week.is_some() => {
+                exit_with_usage("Incorrect number of arguments.")
+            }
+            "all" => all = true,
+            "batch" => batch = true,
+            _ => match parse_argument(&arg) {
+                Some(date) => week = Some(date),
+                None => exit_with_usage(&format!("Invalid argument `{arg}`.")),
+            },
+        }
+    }
+
+    let from = from.map(|(date, time)| server_time(date, time.unwrap_or(NaiveTime::MIN)));
+    let to = to.map(|(date, time)| match time {
+        Some(time) => server_time(date, time),
+        None => server_time(date + Days::new(1), NaiveTime::MIN),
+    });
+    let week = week.map(|(date, time)| {
+        week_containing(server_time(date, time.unwrap_or(CONFIG.week.start_time)))
+    });
+
+    let mode = match (all, batch, week, from, to) {
+        (true, false, None, None, None) => Mode::All,
+        (false, true, None, None, None) => Mode::Batch,
+        (false, false, Some((start, end)), None, None) => Mode::Range(Some(start), end),
+        (false, false, None, Some(from), to) => Mode::Range(
+            Some(from),
+            to.unwrap_or_else(|| from.checked_add_days(Days::new(7)).unwrap()),
+        ),
+        (false, false, None, None, Some(to)) => Mode::Range(None, to),
+        // These read the whole of timers.txt, so don't need a period
+        (false, false, None, None, None) if fix || dry_run_aliases => Mode::All,
+        _ => exit_with_usage("Incorrect number of arguments."),
+    };
+
+    Args {
+        mode,
+        reference_date,
+        dry_run_aliases,
+        explain,
+        member,
+        all_balances,
+        fix,
+        edit,
+    }
+}
@@ -0,0 +1,81 @@
+use crate::normalise::normalise;
+use chrono::{NaiveTime, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::{fs::File, io::BufReader};
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    pub timezone: Tz,
+    pub week: Week,
+    pub timestamps: Timestamps,
+    // Names that can be written together as `@<group>` in timers.txt
+    #[serde(default)]
+    pub groups: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Week {
+    pub start_day: Weekday,
+    pub start_time: NaiveTime,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Timestamps {
+    numeric_date: NumericDate,
+    formats: Vec<Format>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Format {
+    Plain(String),
+    WithTimezone { format: String, timezone: Tz },
+}
+
+#[derive(Deserialize, Debug)]
+enum NumericDate {
+    #[serde(rename = "dd/mm/yyyy")]
+    DayFirst,
+    #[serde(rename = "mm/dd/yyyy")]
+    MonthFirst,
+}
+
+impl Config {
+    pub fn group(&self, name: &str) -> Option<&Vec<String>> {
+        self.groups
+            .iter()
+            .find(|(group, _)| normalise(group) == name)
+            .map(|(_, names)| names)
+    }
+}
+
+impl Timestamps {
+    // Each format with the timezone its times are written in, if it differs from the server's
+    pub fn formats(&self) -> Vec<(String, Option<Tz>)> {
+        let numeric_date = match self.numeric_date {
+            NumericDate::DayFirst => "%d/%m/%Y",
+            NumericDate::MonthFirst => "%m/%d/%Y",
+        };
+
+        self.formats
+            .iter()
+            .map(|f| match f {
+                Format::Plain(format) => (format.replace("{numeric_date}", numeric_date), None),
+                Format::WithTimezone { format, timezone } => (
+                    format.replace("{numeric_date}", numeric_date),
+                    Some(*timezone),
+                ),
+            })
+            .collect()
+    }
+}
+
+lazy_static! {
+    pub static ref CONFIG: Config = {
+        let config_input = File::open("config.json").expect("Cannot find config.json");
+        serde_json::from_reader(BufReader::new(config_input))
+            .expect("Cannot load settings from config.json")
+    };
+}
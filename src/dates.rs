@@ -0,0 +1,238 @@
+use crate::config::CONFIG;
+use crate::normalise::normalise;
+use chrono::{DateTime, Datelike, Days, LocalResult, TimeDelta, TimeZone};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use chrono_tz::Tz;
+
+pub type Timestamp = DateTime<Tz>;
+
+const TIME_FORMATS: [&str; 2] = ["%H:%M", "%I:%M %p"];
+
+// "last monday at 7:05 pm"
+const RELATIVE_TOKENS: usize = 5;
+
+lazy_static! {
+    static ref FORMATS: Vec<(String, Tz)> = CONFIG
+        .timestamps
+        .formats()
+        .into_iter()
+        .map(|(format, timezone)| (format, timezone.unwrap_or(CONFIG.timezone)))
+        .collect();
+}
+
+lazy_static! {
+    static ref MAX_TOKENS: usize = FORMATS
+        .iter()
+        .map(|(f, _)| f.split_whitespace().count())
+        .fold(RELATIVE_TOKENS, usize::max);
+}
+
+fn parse_relative(candidate: &str, today: NaiveDate) -> Option<NaiveDateTime> {
+    let (day, time) = candidate.split_once(" at ")?;
+
+    let date = match day {
+        "today" => today,
+        "yesterday" => today.pred_opt()?,
+        _ => {
+            let weekday: Weekday = day.strip_prefix("last ")?.parse().ok()?;
+
+            let today_index = today.weekday().num_days_from_monday();
+            let days_back = match (7 + today_index - weekday.num_days_from_monday()) % 7 {
+                0 => 7,
+                days => days,
+            };
+
+            today.checked_sub_days(Days::new(days_back.into()))?
+        }
+    };
+
+    let time = TIME_FORMATS
+        .iter()
+        .find_map(|fmt| NaiveTime::parse_from_str(time, fmt).ok())?;
+
+    Some(date.and_time(time))
+}
+
+/// Converts a wall-clock time in `timezone` to server time. Times repeated when the clocks go
+/// back resolve to the first occurrence, and times skipped when they go forward are moved past
+/// the gap.
+pub fn to_server_time(time: NaiveDateTime, timezone: Tz) -> Option<Timestamp> {
+    let local = match timezone.from_local_datetime(&time) {
+        LocalResult::Single(local) => local,
+        LocalResult::Ambiguous(earliest, _) => earliest,
+        LocalResult::None => timezone
+            .from_local_datetime(&time.checked_add_signed(TimeDelta::hours(1))?)
+            .earliest()?,
+    };
+
+    Some(local.with_timezone(&CONFIG.timezone))
+}
+
+/// Returns the start and end of the configured week that `time` falls in.
+pub fn week_containing(time: Timestamp) -> (Timestamp, Timestamp) {
+    let week = &CONFIG.week;
+
+    let date = time.date_naive();
+    let days_back =
+        (7 + date.weekday().num_days_from_monday() - week.start_day.num_days_from_monday()) % 7;
+
+    let mut start = date - Days::new(days_back.into());
+
+    if start.and_time(week.start_time) > time.naive_local() {
+        start = start - Days::new(7);
+    }
+
+    let end = start + Days::new(7);
+
+    (
+        to_server_time(start.and_time(week.start_time), CONFIG.timezone).unwrap(),
+        to_server_time(end.and_time(week.start_time), CONFIG.timezone).unwrap(),
+    )
+}
+
+// The timestamp, and whether it was written relative to `today`, e.g. "today at 19:05"
+fn parse_timestamp(candidate: &str, today: NaiveDate) -> Option<(Timestamp, bool)> {
+    // Lines are lowercased, so literals such as the "T" in ISO 8601 need the uppercase form
+    let uppercase = candidate.to_uppercase();
+
+    let (time, timezone, relative) = FORMATS
+        .iter()
+        .find_map(|(fmt, timezone)| {
+            NaiveDateTime::parse_from_str(candidate, fmt)
+                .or_else(|_| NaiveDateTime::parse_from_str(&uppercase, fmt))
+                .ok()
+                .map(|time| (time, *timezone, false))
+        })
+        .or_else(|| Some((parse_relative(candidate, today)?, CONFIG.timezone, true)))?;
+
+    Some((to_server_time(time, timezone)?, relative))
+}
+
+/// Finds the timestamp at the start of `line`, returning it along with the byte offset at
+/// which the rest of the line begins.
+pub fn parse_date_prefix(line: &str, today: NaiveDate) -> Option<(Timestamp, usize)> {
+    find_date_prefix(line, today).map(|(time, offset, _)| (time, offset))
+}
+
+fn find_date_prefix(line: &str, today: NaiveDate) -> Option<(Timestamp, usize, bool)> {
+    let mut tokens = Vec::<(usize, usize)>::new();
+    let mut start = None;
+
+    for (i, c) in line.char_indices() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                tokens.push((s, i));
+                start = None;
+            }
+            _ => (),
+        }
+
+        if tokens.len() == *MAX_TOKENS {
+            break;
+        }
+    }
+
+    if let Some(s) = start {
+        if tokens.len() < *MAX_TOKENS {
+            tokens.push((s, line.len()));
+        }
+    }
+
+    for count in (1..=tokens.len()).rev() {
+        let candidate = tokens[..count]
+            .iter()
+            .map(|&(s, e)| &line[s..e])
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        if let Some((date, relative)) = parse_timestamp(&candidate, today) {
+            let end = tokens[count - 1].1;
+            let offset = line[end..]
+                .find(|c: char| !c.is_whitespace())
+                .map_or(line.len(), |i| end + i);
+
+            return Some((date, offset, relative));
+        }
+    }
+
+    None
+}
+
+/// Rewrites a relative date at the start of a line, e.g. "Today at 19:05", as the date it stands
+/// for in the first timestamp format from config.json. Relative dates are read against the time
+/// timers.txt was last changed, so they must be resolved whenever the tool writes the file.
+pub fn resolve_relative_date(line: &str, today: NaiveDate) -> String {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let tidied = words
+        .iter()
+        .map(|w| normalise(w))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    let Some((time, offset, true)) = find_date_prefix(&tidied, today) else {
+        return line.to_string();
+    };
+
+    let (format, timezone) = &FORMATS[0];
+    let date = time.with_timezone(timezone).format(format);
+    let indent = &line[..line.len() - line.trim_start().len()];
+
+    // Everything after the date is kept as written
+    match words.get(tidied[..offset].split_whitespace().count()) {
+        Some(word) => {
+            let start = word.as_ptr() as usize - line.as_ptr() as usize;
+            format!("{indent}{date} {}", &line[start..])
+        }
+        None => format!("{indent}{date}"),
+    }
+}
+
+pub fn get_date(line: &str, today: NaiveDate) -> Option<Timestamp> {
+    parse_date_prefix(line, today).map(|(date, _)| date)
+}
+
+// Strips the time from a timestamp format, e.g. "%d %b %Y at %H:%M" becomes "%d %b %Y"
+fn date_part(fmt: &str) -> &str {
+    let end = ["%H", "%I", "%R", "%T"]
+        .iter()
+        .filter_map(|t| fmt.find(t))
+        .min()
+        .unwrap_or(fmt.len());
+
+    fmt[..end]
+        .trim_end()
+        .trim_end_matches(" at")
+        .trim_end_matches(['T', ','])
+}
+
+/// Parses a date given on the command line using any of the configured timestamp formats. The
+/// time may be left out, in which case `None` is returned alongside the date.
+pub fn parse_argument(arg: &str) -> Option<(NaiveDate, Option<NaiveTime>)> {
+    for (fmt, _) in FORMATS.iter() {
+        if let Ok(date) = NaiveDateTime::parse_from_str(arg, fmt) {
+            return Some((date.date(), Some(date.time())));
+        }
+
+        let date_fmt = date_part(fmt);
+
+        if let Ok(date) = NaiveDateTime::parse_from_str(arg, &format!("{date_fmt} %H:%M")) {
+            return Some((date.date(), Some(date.time())));
+        }
+
+        if let Ok(date) = NaiveDate::parse_from_str(arg, date_fmt) {
+            return Some((date, None));
+        }
+    }
+
+    None
+}
+
+/// Parses a date written in a config file, at midnight server time if no time is given.
+pub fn parse_server_time(text: &str) -> Option<Timestamp> {
+    let (date, time) = parse_argument(text)?;
+    to_server_time(
+        date.and_time(time.unwrap_or(NaiveTime::MIN)),
+        CONFIG.timezone,
+    )
+}
@@ -0,0 +1,70 @@
+use crate::normalise::normalise;
+
+pub enum Directive {
+    Adjust {
+        name: String,
+        points: i32,
+        reason: String,
+    },
+    IgnoreNext,
+    Comment,
+}
+
+/// Reads a `# comment` or `!directive` line from timers.txt, returning `None` for any other line.
+///
+/// `!adjust <name> <points> <reason>` gives or takes points by hand, and `!ignore-next` leaves
+/// out the next line that isn't a comment.
+pub fn parse_directive(line: &str) -> Option<Result<Directive, String>> {
+    if line.starts_with('#') {
+        return Some(Ok(Directive::Comment));
+    }
+
+    let rest = line.strip_prefix('!')?;
+
+    let directive = match next_word(rest) {
+        Some((word, rest)) => match normalise(word).as_str() {
+            "adjust" => parse_adjust(rest),
+            "ignore-next" => match next_word(rest) {
+                Some(_) => Err("`!ignore-next` doesn't take anything after it".to_string()),
+                None => Ok(Directive::IgnoreNext),
+            },
+            _ => Err(format!("unknown directive `!{word}`")),
+        },
+        None => Err("missing directive after `!`".to_string()),
+    };
+
+    Some(directive)
+}
+
+// The first word of the text along with everything after it
+fn next_word(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    if text.is_empty() {
+        return None;
+    }
+
+    Some(text.split_at(text.find(char::is_whitespace).unwrap_or(text.len())))
+}
+
+fn parse_adjust(rest: &str) -> Result<Directive, String> {
+    let Some((name, rest)) = next_word(rest) else {
+        return Err("`!adjust` is missing a name".to_string());
+    };
+
+    let (points, rest) = match next_word(rest).map(|(p, rest)| (p.parse::<i32>(), rest)) {
+        Some((Ok(points), rest)) => (points, rest),
+        Some((Err(_), _)) | None => return Err(format!("`!adjust` is missing points for {name}")),
+    };
+
+    // The reason is kept exactly as written
+    let reason = rest.trim();
+    if reason.is_empty() {
+        return Err(format!("`!adjust` is missing a reason for {name}"));
+    }
+
+    Ok(Directive::Adjust {
+        name: name.to_string(),
+        points,
+        reason: reason.to_string(),
+    })
+}
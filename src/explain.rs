@@ -0,0 +1,107 @@
+use crate::ledger::{Entry, Ledger, Reason};
+use crate::normalise::normalise;
+use colored::*;
+use std::fs::File;
+use std::io::Write;
+
+fn describe(entry: &Entry) -> String {
+    let (credit, group, deduction) = match &entry.reason {
+        Reason::Kill { credit, group } => (credit, group, false),
+        Reason::Not { credit, group } => (credit, group, true),
+        Reason::Adjustment { reason, line } => {
+            let line = line.map_or(String::new(), |l| format!(", line {}", l + 1));
+
+            return format!(
+                "{}{line}, {reason}, {:+}",
+                entry.time.format("%Y-%m-%d %H:%M"),
+                entry.points
+            );
+        }
+    };
+
+    let mut modifiers: String = credit.modifiers.iter().map(|m| format!("({m})")).collect();
+    if !modifiers.is_empty() {
+        modifiers.insert(0, ' ');
+    }
+
+    // Bosses read through a pattern show the token, as the points depend on it
+    let mut boss = credit.boss.clone();
+    if credit.token != credit.key {
+        boss.push_str(&format!(" [{}]", credit.token));
+    }
+
+    let mut workings = format!("{} base", credit.base);
+    if !credit.modifiers.is_empty() {
+        workings.push_str(&format!(", {:+} from modifiers", credit.effect));
+    }
+    if deduction {
+        workings.push_str(", \"not\" deduction");
+    }
+    if let Some(group) = group {
+        workings.push_str(&format!(", as part of @{group}"));
+    }
+
+    format!(
+        "{}, line {}, {boss}{modifiers}: {workings}, {:+}",
+        entry.time.format("%Y-%m-%d %H:%M"),
+        credit.index + 1,
+        entry.points
+    )
+}
+
+// Lines from timers.txt come first, with any adjustments listed after them
+fn statement(name: &str, entries: &[&Entry]) -> String {
+    let total: i32 = entries.iter().map(|e| e.points).sum();
+
+    let (adjustments, lines): (Vec<&Entry>, Vec<&Entry>) = entries
+        .iter()
+        .partition(|e| matches!(e.reason, Reason::Adjustment { .. }));
+
+    let mut statement = format!("{name}, total {total}\n");
+    for entry in lines {
+        statement.push_str(&format!("  {}\n", describe(entry)));
+    }
+
+    if !adjustments.is_empty() {
+        let adjusted: i32 = adjustments.iter().map(|e| e.points).sum();
+
+        statement.push_str(&format!("  Adjustments, {adjusted:+}\n"));
+        for entry in adjustments {
+            statement.push_str(&format!("    {}\n", describe(entry)));
+        }
+    }
+
+    statement
+}
+
+/// Writes every member's statement, in the same order as the output file.
+pub fn write_explain(path: &str, ledger: &Ledger) {
+    let members = ledger.by_member();
+
+    let mut names: Vec<&&str> = members.keys().collect();
+    names.sort_by_key(|n| n.to_lowercase());
+
+    let mut f = File::create(path).expect("Failed to create explain file");
+
+    for name in names {
+        f.write_all(format!("{}\n", statement(name, &members[*name])).as_bytes())
+            .expect("Failed to write to explain file");
+    }
+}
+
+pub fn print_member(ledger: &Ledger, member: &str) {
+    let member = normalise(member);
+
+    match ledger
+        .by_member()
+        .into_iter()
+        .find(|(name, _)| normalise(name) == member)
+    {
+        Some((name, entries)) => print!("\n{}", statement(name, &entries)),
+        None => println!(
+            "\n{} no points were given to or taken from {}",
+            "Warning:".yellow().bold(),
+            member.bold()
+        ),
+    }
+}
@@ -0,0 +1,431 @@
+use crate::adjustments::Adjustment;
+use crate::boss_aliases::rewrite_boss;
+use crate::catalog::{catalog, Alias};
+use crate::cli::Args;
+use crate::dates::{parse_date_prefix, resolve_relative_date};
+use crate::directives::parse_directive;
+use crate::normalise::normalise;
+use crate::points::{get_points, PointsError};
+use crate::sanitise::{
+    print_errors, read_lines, reference_date, rewrite_line, tidy_line, validate, ErrorKind,
+    Invalid, Kill, Line, LineError,
+};
+use crate::{clear, input};
+use chrono::NaiveDate;
+use colored::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read_to_string, write};
+use std::iter;
+use std::process;
+
+fn print_line_errors(errors: &[LineError], index: usize) {
+    for error in errors.iter().filter(|e| e.index == index) {
+        match &error.reason {
+            Some(reason) => println!("{} ({})", error.kind.describe(), reason),
+            None => println!("{}", error.kind.describe()),
+        }
+    }
+}
+
+// Rewrites timers.txt with the changed lines in place of the original ones
+fn write_edits(original: &[&str], edits: &BTreeMap<usize, String>, today: NaiveDate) {
+    let mut contents = String::new();
+    for (index, line) in original.iter().enumerate() {
+        let line = edits.get(&index).map_or(*line, |e| e.as_str());
+        contents.push_str(&resolve_relative_date(line, today));
+        contents.push('\n');
+    }
+
+    write("timers.txt", contents).expect("Cannot write timers.txt");
+}
+
+// Puts the officer's text in place of a line, as it would have been read from timers.txt
+fn replace_line(lines: &mut Vec<Line>, index: usize, text: &str, args: &Args, today: NaiveDate) {
+    let tidied = tidy_line(text);
+    let position = lines.iter().position(|(i, _)| *i == index);
+
+    match (position, tidied.is_empty()) {
+        (Some(position), true) => {
+            lines.remove(position);
+        }
+        (Some(position), false) => lines[position].1 = rewrite_line(index, tidied, args, today),
+        (None, true) => (),
+        (None, false) => {
+            let position = lines.partition_point(|(i, _)| *i < index);
+            lines.insert(position, (index, rewrite_line(index, tidied, args, today)));
+        }
+    }
+}
+
+/// Walks through each invalid line, letting the officer edit it, comment it out so it is
+/// ignored, or skip it. Every change is checked again straight away, and timers.txt is only
+/// rewritten once the officer confirms it.
+pub fn fix_lines(invalid: Invalid, args: &Args) -> Option<(Vec<Kill>, Vec<Adjustment>)> {
+    let Invalid {
+        mut lines,
+        errors,
+        today,
+    } = invalid;
+
+    let original = read_to_string("timers.txt").expect("Cannot find timers.txt");
+    let original: Vec<&str> = original.lines().collect();
+
+    let invalid: BTreeSet<usize> = errors.iter().map(|e| e.index).collect();
+    let mut edits = BTreeMap::<usize, String>::new();
+    let (mut kills, mut adjustments, mut errors) = (Vec::new(), Vec::new(), errors);
+
+    for index in invalid {
+        let mut checked = false;
+
+        loop {
+            let text = edits
+                .get(&index)
+                .map_or(original.get(index).copied().unwrap_or(""), |t| t.as_str());
+
+            if !errors.iter().any(|e| e.index == index) {
+                println!("\nLine {} is now valid.", index + 1);
+                input("Press Enter to continue: ");
+                break;
+            }
+
+            clear();
+            if checked {
+                println!("Line {} is still invalid.\n", index + 1);
+            }
+            println!(
+                "Line {}:
+{}
+",
+                index + 1,
+                text.bold()
+            );
+            print_line_errors(&errors, index);
+            println!();
+            println!("Edit the line (1)");
+            println!("Ignore the line (2)");
+            println!("Skip (Enter)");
+            println!("Enter q to quit");
+
+            let edit = match input("Select a choice (1-2): ").as_str() {
+                "q" => process::exit(1),
+                "1" => input("Enter the new line: "),
+                "2" => format!("# {text}"),
+                "" => break,
+                _ => {
+                    println!("Invalid input, please enter 1, 2, q, or nothing.");
+                    continue;
+                }
+            };
+
+            replace_line(&mut lines, index, &edit, args, today);
+            edits.insert(index, edit);
+
+            (kills, adjustments, errors) = validate(&lines, today);
+            checked = true;
+        }
+    }
+
+    if !edits.is_empty() {
+        println!();
+        for (index, edit) in edits.iter() {
+            println!("{}: {}", index + 1, edit);
+        }
+
+        let answer = input(&format!(
+            "\nWrite {} changed lines to timers.txt? (y/n): ",
+            edits.len()
+        ));
+
+        if answer == "y" {
+            write_edits(&original, &edits, today);
+        } else {
+            println!("timers.txt left unchanged.");
+        }
+    }
+
+    if errors.is_empty() {
+        Some((kills, adjustments))
+    } else {
+        println!();
+        print_errors(&errors);
+        None
+    }
+}
+
+// How many letters a boss can be out by and still be suggested, so short tokens can have one
+fn allowed_distance(boss: &str) -> usize {
+    match boss.chars().count() {
+        0..=2 => 0,
+        3..=5 => 1,
+        6..=9 => 2,
+        _ => 3,
+    }
+}
+
+// The catalog boss closest in spelling to an unknown boss, by its key, aliases or name
+fn closest_boss(boss: &str) -> Option<String> {
+    if !matches!(get_points(boss, &[], None), Err(PointsError::UnknownBoss)) {
+        return None;
+    }
+
+    catalog()
+        .bosses
+        .iter()
+        .flat_map(|b| {
+            let aliases = b.aliases.iter().filter_map(|alias| match alias {
+                Alias::Token(token) => Some(token.clone()),
+                Alias::Regex { .. } => None,
+            });
+            let name = normalise(&b.name.split_whitespace().collect::<String>());
+
+            iter::once(b.key.clone())
+                .chain(aliases)
+                .chain(iter::once(name))
+                .map(|spelling| (textdistance::str::levenshtein(&spelling, boss), &b.key))
+        })
+        .filter(|(distance, _)| *distance <= allowed_distance(boss))
+        .min()
+        .map(|(_, key)| key.clone())
+}
+
+// A word of the line as written, with the space before it, along with how it reads once tidied
+#[derive(Clone)]
+struct Word {
+    space: String,
+    raw: String,
+    tidy: String,
+}
+
+impl Word {
+    fn new(space: &str, raw: &str) -> Self {
+        Word {
+            space: space.to_string(),
+            raw: raw.to_string(),
+            tidy: normalise(raw),
+        }
+    }
+
+    fn is_name(&self) -> bool {
+        !matches!(self.tidy.as_str(), "not" | "except") && !self.tidy.starts_with(['@', '"'])
+    }
+
+    fn join(&mut self, other: &Word) {
+        self.raw.push_str(&other.raw);
+        self.tidy.push_str(&other.tidy);
+    }
+}
+
+// Merges each single character into the name before it, or after it if it comes first
+fn merge_single_characters(words: Vec<Word>) -> Vec<Word> {
+    let mut merged = Vec::<Word>::new();
+    let mut pending: Option<Word> = None;
+
+    for word in words {
+        if word.tidy.chars().count() == 1 && word.is_name() {
+            match (merged.last_mut(), pending.as_mut()) {
+                (Some(last), None) if last.is_name() && !last.tidy.ends_with('"') => {
+                    last.join(&word)
+                }
+                (_, Some(pending)) => pending.join(&word),
+                (_, None) => pending = Some(word),
+            }
+        } else if word.is_name() && pending.is_some() {
+            let mut pending = pending.take().unwrap();
+            pending.join(&word);
+            merged.push(pending);
+        } else {
+            merged.extend(pending.take());
+            merged.push(word);
+        }
+    }
+
+    merged.extend(pending);
+    merged
+}
+
+// Puts the names back in the order `<credited> except <names> not <names>`, joining repeated
+// sections and dropping keywords with nobody after them
+fn reorder_roll(words: Vec<Word>) -> Vec<Word> {
+    let mut sections: [Vec<Word>; 3] = Default::default();
+    let mut keywords: [Option<Word>; 3] = Default::default();
+    let mut section = 0;
+    let mut quoted = false;
+
+    for word in words {
+        match word.tidy.as_str() {
+            "except" | "not" if !quoted => {
+                section = if word.tidy == "except" { 1 } else { 2 };
+                keywords[section].get_or_insert(word);
+            }
+            _ => {
+                let opens = !quoted && word.tidy.starts_with('"');
+                let rest = if opens { &word.tidy[1..] } else { &word.tidy };
+                quoted = (quoted || opens) && !rest.ends_with('"');
+
+                sections[section].push(word);
+            }
+        }
+    }
+
+    let [credited, excepted, penalised] = sections;
+    let [_, except, not] = keywords;
+    let mut words = credited;
+
+    for (keyword, names) in [(except, excepted), (not, penalised)] {
+        if let (Some(keyword), false) = (keyword, names.is_empty()) {
+            words.push(keyword);
+            words.extend(names);
+        }
+    }
+
+    words
+}
+
+// A rewrite of the line as written, fixing only the mistakes the validator found in it
+fn propose(line: &str, kinds: &[ErrorKind], today: NaiveDate) -> Option<String> {
+    if parse_directive(line.trim()).is_some() {
+        return None;
+    }
+
+    let mut words = Vec::<(usize, Word)>::new();
+    let mut end = 0;
+    for raw in line.split_whitespace() {
+        let start = raw.as_ptr() as usize - line.as_ptr() as usize;
+        words.push((start, Word::new(&line[end..start], raw)));
+        end = start + raw.len();
+    }
+
+    // The date is left as written, up to the first word after it
+    let tidied = words
+        .iter()
+        .map(|(_, w)| w.tidy.as_str())
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ");
+    let offset = parse_date_prefix(&tidied, today).map_or(0, |(_, offset)| offset);
+    let mut date_words = tidied[..offset].split_whitespace().count();
+    let payload = words
+        .iter()
+        .position(|(_, w)| {
+            if date_words == 0 {
+                return true;
+            }
+            if !w.tidy.is_empty() {
+                date_words -= 1;
+            }
+            false
+        })
+        .unwrap_or(words.len());
+
+    let (start, boss) = words.get(payload).cloned()?;
+    let mut rest: Vec<Word> = words[payload + 1..]
+        .iter()
+        .map(|(_, w)| w.clone())
+        .collect();
+
+    let mut boss_raw = boss.raw.clone();
+    if kinds.contains(&ErrorKind::Boss) {
+        let tidy = rewrite_boss(&boss.tidy).unwrap_or(boss.tidy.clone());
+        let base = &tidy[..tidy.find('(').unwrap_or(tidy.len())];
+
+        if let Some(key) = closest_boss(base) {
+            boss_raw = format!(
+                "{key}{}",
+                &boss.raw[boss.raw.find('(').unwrap_or(boss.raw.len())..]
+            );
+        }
+    }
+
+    let modifiers = rest.iter().take_while(|w| w.tidy.starts_with('(')).count();
+    let mut names = rest.split_off(modifiers);
+
+    if kinds.contains(&ErrorKind::At) {
+        names.retain(|w| w.tidy != "at");
+    }
+    if kinds.contains(&ErrorKind::SingleCharacter) {
+        names = merge_single_characters(names);
+    }
+    if kinds.contains(&ErrorKind::Roll) {
+        names = reorder_roll(names);
+    }
+
+    // Words keep the space written before them, so only the fixed words change
+    let mut fixed = format!("{}{boss_raw}", &line[..start]);
+    for word in rest.iter().chain(names.iter()) {
+        fixed.push_str(&word.space);
+        fixed.push_str(&word.raw);
+    }
+
+    (fixed != line).then_some(fixed)
+}
+
+/// Proposes a rewrite for each invalid line that a common mistake explains, showing it as a diff
+/// and writing the changes the officer accepts to timers.txt.
+pub fn suggest_fixes(args: &Args) {
+    let today = reference_date(args);
+    let mut lines = read_lines(args, today);
+
+    let original = read_to_string("timers.txt").expect("Cannot find timers.txt");
+    let original: Vec<&str> = original.lines().collect();
+
+    let (_, _, errors) = validate(&lines, today);
+    let invalid: BTreeSet<usize> = errors.iter().map(|e| e.index).collect();
+
+    let mut edits = BTreeMap::<usize, String>::new();
+
+    'lines: for index in invalid {
+        // Fixing one mistake can uncover another, e.g. a stray `at` after an unknown boss
+        let mut fixed = original[index].to_string();
+        let mut fixed_lines = lines.clone();
+
+        loop {
+            let (_, _, errors) = validate(&fixed_lines, today);
+            let kinds: Vec<ErrorKind> = errors
+                .iter()
+                .filter(|e| e.index == index)
+                .map(|e| e.kind)
+                .collect();
+
+            match propose(&fixed, &kinds, today) {
+                Some(next) => fixed = next,
+                None => break,
+            }
+            replace_line(&mut fixed_lines, index, &fixed, args, today);
+        }
+
+        if fixed == original[index] {
+            continue;
+        }
+
+        println!("\nLine {}:", index + 1);
+        println!("{}", format!("- {}", original[index]).red());
+        println!("{}", format!("+ {fixed}").green());
+
+        let (_, _, remaining) = validate(&fixed_lines, today);
+        if remaining.iter().any(|e| e.index == index) {
+            println!("\nStill invalid after this change:");
+            print_line_errors(&remaining, index);
+        }
+
+        loop {
+            match input("Apply this change? (y/n, q to stop): ").as_str() {
+                "y" => {
+                    edits.insert(index, fixed);
+                    lines = fixed_lines;
+                    break;
+                }
+                "n" | "" => break,
+                "q" => break 'lines,
+                _ => println!("Invalid input, please enter y, n or q."),
+            }
+        }
+    }
+
+    if edits.is_empty() {
+        println!("\nNo changes made to timers.txt.");
+        return;
+    }
+
+    write_edits(&original, &edits, today);
+    println!("\nWrote {} changed lines to timers.txt.", edits.len());
+}
@@ -0,0 +1,111 @@
+use crate::dates::{week_containing, Timestamp};
+use crate::points::Score;
+use std::collections::{BTreeMap, HashMap};
+
+/// The line of timers.txt that points came from, and how they were worked out.
+#[derive(Clone)]
+pub struct Credit {
+    pub index: usize,
+    pub boss: String,
+    pub key: String,
+    pub token: String,
+    pub modifiers: Vec<String>,
+    pub base: i32,
+    pub effect: i32,
+}
+
+impl Credit {
+    pub fn new(score: &Score, index: usize) -> Credit {
+        Credit {
+            index,
+            boss: score.boss.name.clone(),
+            key: score.boss.key.clone(),
+            token: score.token.clone(),
+            modifiers: score.modifiers.iter().map(|m| m.key.clone()).collect(),
+            base: score.base,
+            effect: score.points - score.base,
+        }
+    }
+}
+
+// Kills and "not" deductions keep the group the member was named through, if any
+pub enum Reason {
+    Kill {
+        credit: Credit,
+        group: Option<String>,
+    },
+    Not {
+        credit: Credit,
+        group: Option<String>,
+    },
+    Adjustment {
+        reason: String,
+        line: Option<usize>,
+    },
+}
+
+/// Points given to, or taken from if negative, one member.
+pub struct Entry {
+    pub name: String,
+    pub points: i32,
+    pub time: Timestamp,
+    pub reason: Reason,
+}
+
+#[derive(Default)]
+pub struct Ledger {
+    pub entries: Vec<Entry>,
+}
+
+impl Ledger {
+    pub fn record(&mut self, name: &str, points: i32, time: Timestamp, reason: Reason) {
+        self.entries.push(Entry {
+            name: name.to_string(),
+            points,
+            time,
+            reason,
+        });
+    }
+
+    fn sum<'a>(entries: impl Iterator<Item = &'a Entry>) -> HashMap<String, i32> {
+        let mut totals = HashMap::<String, i32>::new();
+
+        for entry in entries {
+            *totals.entry(entry.name.clone()).or_default() += entry.points;
+        }
+
+        totals
+    }
+
+    pub fn totals(&self) -> HashMap<String, i32> {
+        Ledger::sum(self.entries.iter())
+    }
+
+    /// Totals for each week, as set in config.json, keyed by the start of the week.
+    pub fn weekly_totals(&self) -> BTreeMap<Timestamp, HashMap<String, i32>> {
+        let mut weeks = BTreeMap::<Timestamp, Vec<&Entry>>::new();
+
+        for entry in self.entries.iter() {
+            weeks
+                .entry(week_containing(entry.time).0)
+                .or_default()
+                .push(entry);
+        }
+
+        weeks
+            .into_iter()
+            .map(|(week, entries)| (week, Ledger::sum(entries.into_iter())))
+            .collect()
+    }
+
+    /// Every entry for each member, in the order they were recorded.
+    pub fn by_member(&self) -> HashMap<&str, Vec<&Entry>> {
+        let mut members = HashMap::<&str, Vec<&Entry>>::new();
+
+        for entry in self.entries.iter() {
+            members.entry(&entry.name).or_default().push(entry);
+        }
+
+        members
+    }
+}
@@ -3,15 +3,34 @@ extern crate lazy_static;
 extern crate google_sheets4 as sheets4;
 
 use autocorrect::Autocorrecter;
+use cli::{Args, Mode};
 use colored::*;
+use dates::Timestamp;
+use ledger::{Credit, Ledger, Reason};
+use normalise::normalise;
+use roll::Member;
+use sanitise::Kill;
 use serde_json::from_reader;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{File, OpenOptions};
 use std::io::{self, stdout, BufReader, Write};
 use std::process;
 
+pub mod adjustments;
 pub mod autocorrect;
+pub mod boss_aliases;
+pub mod catalog;
+pub mod cli;
+pub mod config;
+pub mod dates;
+pub mod directives;
+pub mod explain;
+pub mod fixup;
+pub mod ledger;
+pub mod migrate;
+pub mod normalise;
 pub mod points;
+pub mod roll;
 pub mod sanitise;
 pub mod sheets;
 
@@ -22,25 +41,29 @@ fn clear() {
 fn build_aliases(names: Vec<String>) -> HashMap<String, String> {
     let aliases_input = File::open("name_aliases.json").expect("Cannot find name_aliases.json");
 
-    let mut aliases: HashMap<String, String> = from_reader(BufReader::new(aliases_input))
+    let aliases: HashMap<String, String> = from_reader(BufReader::new(aliases_input))
         .expect("name_aliases.json does not contain valid json");
 
+    let mut aliases: HashMap<String, String> = aliases
+        .into_iter()
+        .map(|(alias, name)| (normalise(&alias), name))
+        .collect();
+
     for name in names {
         if name.contains(' ') {
             let tmp: Vec<&str> = name.split_whitespace().collect();
-            aliases.insert(tmp[0].to_lowercase(), name.clone());
-            aliases.insert(tmp.join("").to_lowercase(), name.clone());
+            aliases.insert(normalise(tmp[0]), name.clone());
+            aliases.insert(normalise(&tmp.join(" ")), name.clone());
+            aliases.insert(normalise(&tmp.join("")), name.clone());
             aliases.insert(
-                tmp.join("")
-                    .trim_end_matches(char::is_numeric)
-                    .to_lowercase(),
+                normalise(tmp.join("").trim_end_matches(char::is_numeric)),
                 name.clone(),
             );
         } else {
-            aliases.insert(name.to_lowercase(), name.clone());
+            aliases.insert(normalise(&name), name.clone());
             if name.parse::<i32>().is_err() {
                 aliases.insert(
-                    name.trim_end_matches(char::is_numeric).to_lowercase(),
+                    normalise(name.trim_end_matches(char::is_numeric)),
                     name.clone(),
                 );
             }
@@ -56,6 +79,70 @@ fn build_aliases(names: Vec<String>) -> HashMap<String, String> {
     aliases
 }
 
+fn create_output(path: &str) -> File {
+    OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(path)
+        .expect("Failed to open or create output file")
+}
+
+fn write_output(path: &str, dkp_count: &HashMap<String, i32>, args: &Args) {
+    let mut dkp_count: Vec<(&String, &i32)> = dkp_count.iter().collect();
+
+    dkp_count.sort_by_key(|(n, _)| n.to_lowercase());
+
+    if !args.all_balances {
+        dkp_count.retain(|(_, p)| **p > 0);
+    }
+
+    let mut f = create_output(path);
+
+    for (n, p) in dkp_count {
+        f.write_all(format!("{}, {}\n", n, p).as_bytes())
+            .expect("Failed to write to output file");
+    }
+}
+
+fn write_summary(dkp_counts: &BTreeMap<Timestamp, HashMap<String, i32>>, args: &Args) {
+    let mut names: Vec<&String> = dkp_counts
+        .values()
+        .flat_map(|c| c.keys())
+        .collect::<HashSet<&String>>()
+        .into_iter()
+        .collect();
+
+    names.sort_by_key(|n| n.to_lowercase());
+
+    let mut f = create_output("summary.txt");
+
+    let weeks: Vec<String> = dkp_counts
+        .keys()
+        .map(|w| w.format("%Y-%m-%d").to_string())
+        .collect();
+
+    f.write_all(format!("Name, {}, Total\n", weeks.join(", ")).as_bytes())
+        .expect("Failed to write to summary file");
+
+    for name in names {
+        let points: Vec<i32> = dkp_counts
+            .values()
+            .map(|c| c.get(name).copied().unwrap_or(0))
+            .collect();
+        let total: i32 = points.iter().sum();
+
+        if total <= 0 && !args.all_balances {
+            continue;
+        }
+
+        let points: Vec<String> = points.iter().map(i32::to_string).collect();
+
+        f.write_all(format!("{}, {}, {}\n", name, points.join(", "), total).as_bytes())
+            .expect("Failed to write to summary file");
+    }
+}
+
 fn input(prompt: &str) -> String {
     print!("{}", prompt);
     let _ = stdout().flush();
@@ -67,198 +154,394 @@ fn input(prompt: &str) -> String {
     answer
 }
 
-#[tokio::main]
-async fn main() {
-    dotenvy::dotenv().expect("Cannot find .env file");
+// Finds the spreadsheet names for a name from timers.txt or adjustments.json, asking for a
+// correction if it isn't already known. Returns no names if it is discarded.
+fn resolve_name(
+    name: &str,
+    place: &str,
+    aliases: &mut HashMap<String, String>,
+    autocorrector: &mut Autocorrecter,
+    discard: &mut HashSet<String>,
+) -> Vec<String> {
+    let mut actual_names = Vec::<String>::new();
+
+    if discard.contains(name) || name.len() <= 1 {
+        return actual_names;
+    }
 
-    let Some(lines) = sanitise::get_valid_lines() else {
-        return;
-    };
+    if let Some(actual_name) = aliases.get(name) {
+        actual_names.push(actual_name.clone())
+    } else {
+        let guesses = autocorrector.correct(name);
+        let splits = autocorrector.segment(name);
+        let choices = 8 + splits.len();
+        clear();
+        println!(
+            "{place}, error found:
+{}
+",
+            name.bold()
+        );
+        println!("Guess (1): {}", guesses[0]);
+        println!("Guess (2): {}", guesses[1]);
+        println!("Guess (3): {}", guesses[2]);
+        println!("Guess (4): {}", guesses[3]);
+        println!("Guess (5): {}", guesses[4]);
+        println!("Enter a different name (6)");
+        println!("Split into several names (7)");
+        println!("Add as new name not already in the spreadsheet (8)");
+        for (i, split) in splits.iter().enumerate() {
+            println!("Split as {} ({})", split.join(" + "), i + 9);
+        }
+        println!("Discard (Enter)");
+        println!("Enter q to quit");
 
-    let Some(names) = sheets::get_names_from_sheets().await else {
-        return;
-    };
+        let mut corrections = Vec::<String>::new();
 
-    let mut aliases = build_aliases(names);
+        let mut answer: String;
 
-    let mut dkp_count = HashMap::<String, i32>::new();
+        loop {
+            let mut valid_input = true;
 
-    let mut autocorrector: Autocorrecter = Autocorrecter::new(aliases.keys().cloned().collect());
-    let mut discard = HashSet::<String>::new();
+            answer = input(&format!("Select a choice (1-{choices}): "));
 
-    for (points, names, index) in lines {
-        let mut actual_names = Vec::<String>::new();
+            let split = answer
+                .parse::<usize>()
+                .ok()
+                .and_then(|choice| splits.get(choice.checked_sub(9)?));
 
-        'names: for name in names {
-            if name == "not" {
-                actual_names.push("not".to_string());
-                continue;
+            if let Some(split) = split {
+                corrections.extend(split.iter().cloned());
+                break;
             }
 
-            if discard.contains(&name) {
-                continue;
+            match answer.as_str() {
+                "q" => process::exit(1),
+                "1" => corrections.push(guesses[0].to_string()),
+                "2" => corrections.push(guesses[1].to_string()),
+                "3" => corrections.push(guesses[2].to_string()),
+                "4" => corrections.push(guesses[3].to_string()),
+                "5" => corrections.push(guesses[4].to_string()),
+                "6" => corrections.push(normalise(&input("Enter the name: "))),
+                "7" => {
+                    let names = input("Enter the names, separated by spaces: ");
+                    corrections.extend(names.split_whitespace().map(normalise));
+
+                    if corrections.is_empty() {
+                        println!("No names entered.");
+                        valid_input = false;
+                    }
+                }
+                "8" => {
+                    let new_name = input("Enter the name: ");
+                    aliases.insert(name.to_string(), new_name.clone());
+                    autocorrector.add_word(new_name.clone());
+                    actual_names.push(new_name);
+                    return actual_names;
+                }
+                "" => {
+                    discard.insert(name.to_string());
+                }
+                _ => {
+                    println!(
+                        "Invalid input, please enter a number between 1 and {choices}, q, or nothing."
+                    );
+                    valid_input = false;
+                }
             }
 
-            if name.len() <= 1 {
-                continue;
+            if valid_input {
+                break;
             }
+        }
 
-            if let Some(actual_name) = aliases.get(&name) {
-                actual_names.push(actual_name.clone())
-            } else {
-                let guesses = autocorrector.correct(&name);
-                clear();
-                println!(
-                    "Line {}, error found:
-{}
-",
-                    index + 1,
-                    name.bold()
-                );
-                println!("Guess (1): {}", guesses[0]);
-                println!("Guess (2): {}", guesses[1]);
-                println!("Guess (3): {}", guesses[2]);
-                println!("Guess (4): {}", guesses[3]);
-                println!("Guess (5): {}", guesses[4]);
-                println!("Enter a different name (6)");
-                println!("Split into two names (7)");
-                println!("Add as new name not already in the spreadsheet (8)");
-                println!("Discard (Enter)");
-                println!("Enter q to quit");
-
-                let mut corrections = Vec::<String>::new();
-
-                let mut answer: String;
-
-                loop {
-                    let mut valid_input = true;
-
-                    answer = input("Select a choice (1-8): ");
-
-                    match answer.as_str() {
-                        "q" => process::exit(1),
-                        "1" => corrections.push(guesses[0].to_string()),
-                        "2" => corrections.push(guesses[1].to_string()),
-                        "3" => corrections.push(guesses[2].to_string()),
-                        "4" => corrections.push(guesses[3].to_string()),
-                        "5" => corrections.push(guesses[4].to_string()),
-                        "6" => corrections.push(input("Enter the name: ")),
-                        "7" => {
-                            corrections.push(input("Enter the first name: "));
-                            corrections.push(input("Enter the second name: "));
-                        }
-                        "8" => {
-                            let new_name = input("Enter the name: ");
-                            aliases.insert(name.clone(), new_name.clone());
-                            autocorrector.add_word(new_name.clone());
-                            continue 'names;
-                        }
-                        "" => {
-                            discard.insert(name.clone());
-                        }
-                        _ => {
-                            println!("Invalid input, please enter a number between 1 and 8, q, or nothing.");
-                            valid_input = false;
-                        }
-                    }
+        let corrections_count = corrections.len();
 
-                    if valid_input {
-                        break;
+        'outer: for mut correction in corrections {
+            loop {
+                if let Some(actual_name) = aliases.get(&correction).cloned() {
+                    if corrections_count == 1 {
+                        aliases.insert(name.to_string(), actual_name.clone());
                     }
-                }
 
-                'outer: for mut correction in corrections {
-                    loop {
-                        if let Some(actual_name) = aliases.get(&correction).cloned() {
-                            if answer != "7" {
-                                aliases.insert(name.clone(), actual_name.clone());
+                    actual_names.push(actual_name.clone());
+                    break;
+                } else {
+                    let correction_guesses = autocorrector.correct(&correction);
+
+                    println!("\nThe name {correction} is invalid.\n");
+                    println!("Guess (1): {}", correction_guesses[0]);
+                    println!("Guess (2): {}", correction_guesses[1]);
+                    println!("Enter a different name (3)");
+                    println!("Discard (4)");
+
+                    let mut valid_input = false;
+                    while !valid_input {
+                        valid_input = true;
+                        let answer2 = input("Select a choice (1-4): ");
+
+                        match answer2.as_str() {
+                            "1" => correction = correction_guesses[0].to_string(),
+                            "2" => correction = correction_guesses[1].to_string(),
+                            "3" => correction = normalise(&input("Enter the name: ")),
+                            "4" => {
+                                discard.insert(name.to_string());
+                                continue 'outer;
                             }
+                            _ => valid_input = false,
+                        }
 
-                            actual_names.push(actual_name.clone());
-                            break;
-                        } else {
-                            let correction_guesses = autocorrector.correct(&correction);
-
-                            println!("\nThe name {correction} is invalid.\n");
-                            println!("Guess (1): {}", correction_guesses[0]);
-                            println!("Guess (2): {}", correction_guesses[1]);
-                            println!("Enter a different name (3)");
-                            println!("Discard (4)");
-
-                            let mut valid_input = false;
-                            while !valid_input {
-                                valid_input = true;
-                                let answer2 = input("Select a choice (1-4): ");
-
-                                match answer2.as_str() {
-                                    "1" => correction = correction_guesses[0].to_string(),
-                                    "2" => correction = correction_guesses[1].to_string(),
-                                    "3" => correction = input("Enter the name: "),
-                                    "4" => {
-                                        discard.insert(name.clone());
-                                        continue 'outer;
-                                    }
-                                    _ => valid_input = false,
-                                }
-
-                                if !valid_input {
-                                    println!("Invalid input. Select a number bewteen 1 and 4.\n");
-                                }
-                            }
+                        if !valid_input {
+                            println!("Invalid input. Select a number bewteen 1 and 4.\n");
                         }
                     }
                 }
             }
         }
+    }
+
+    actual_names
+}
+
+// The longest run of consecutive words, starting at the first, that is a multi-word roster name
+fn match_words(members: &[Member], aliases: &HashMap<String, String>) -> Option<(usize, String)> {
+    let longest = aliases.keys().map(|a| a.split(' ').count()).max()?;
+
+    (2..=longest.min(members.len())).rev().find_map(|length| {
+        let words = &members[..length];
+
+        if words
+            .iter()
+            .any(|m| m.group.is_some() || m.name.contains(' '))
+        {
+            return None;
+        }
+
+        let joined: Vec<&str> = words.iter().map(|m| m.name.as_str()).collect();
+        aliases
+            .get(&joined.join(" "))
+            .map(|name| (length, name.clone()))
+    })
+}
+
+// Each spreadsheet name with the group it was named through, or none if it was also named directly
+fn resolve_names(
+    members: &[Member],
+    index: usize,
+    aliases: &mut HashMap<String, String>,
+    autocorrector: &mut Autocorrecter,
+    discard: &mut HashSet<String>,
+) -> HashMap<String, Option<String>> {
+    let mut resolved = HashMap::<String, Option<String>>::new();
+    let mut start = 0;
+
+    while start < members.len() {
+        let member = &members[start];
+
+        let (length, names) = match match_words(&members[start..], aliases) {
+            Some((length, name)) => (length, vec![name]),
+            None => (
+                1,
+                resolve_name(
+                    &member.name,
+                    &format!("Line {}", index + 1),
+                    aliases,
+                    autocorrector,
+                    discard,
+                ),
+            ),
+        };
+
+        for name in names {
+            let group = resolved.entry(name).or_insert(member.group.clone());
+            if member.group.is_none() {
+                *group = None;
+            }
+        }
+
+        start += length;
+    }
+
+    resolved
+}
+
+#[tokio::main]
+async fn main() {
+    dotenvy::dotenv().expect("Cannot find .env file");
+
+    let args = cli::parse_args();
+
+    if let Err(errors) = catalog::init() {
+        println!("Cannot load bosses.json:");
+        for error in errors {
+            println!("{error}");
+        }
+        return;
+    }
+
+    if args.dry_run_aliases {
+        sanitise::list_alias_rewrites(&args);
+        return;
+    }
 
-        if actual_names.len() == 3 {
-            if actual_names[1] == "not" {
-                dkp_count
-                    .entry(actual_names[0].clone())
-                    .and_modify(|p| *p += points)
-                    .or_insert(points);
+    if args.fix {
+        fixup::suggest_fixes(&args);
+        return;
+    }
 
-                dkp_count
-                    .entry(actual_names[2].clone())
-                    .and_modify(|p| *p -= points)
-                    .or_insert(points);
+    let (lines, inline_adjustments) = match sanitise::get_valid_lines(&args) {
+        Ok(valid) => valid,
+        Err(invalid) if args.edit => match fixup::fix_lines(invalid, &args) {
+            Some(valid) => valid,
+            None => return,
+        },
+        Err(_) => return,
+    };
 
+    let mut adjustments = match adjustments::read_adjustments(&args) {
+        Ok(adjustments) => adjustments,
+        Err(errors) => {
+            println!("Cannot load {}:", adjustments::ADJUSTMENTS_FILE);
+            for error in errors {
+                println!("{error}");
+            }
+            return;
+        }
+    };
+    adjustments.extend(inline_adjustments);
+
+    let Some(names) = sheets::get_names_from_sheets().await else {
+        return;
+    };
+
+    boss_aliases::warn_roster_collisions(&names);
+
+    let mut aliases = build_aliases(names);
+
+    let mut ledger = Ledger::default();
+
+    let mut autocorrector: Autocorrecter = Autocorrecter::new(aliases.keys().cloned().collect());
+    let mut discard = HashSet::<String>::new();
+
+    for Kill {
+        score,
+        roll,
+        index,
+        time,
+    } in lines
+    {
+        let points = score.points;
+        let credit = Credit::new(&score, index);
+
+        let credited = resolve_names(
+            &roll.credited,
+            index,
+            &mut aliases,
+            &mut autocorrector,
+            &mut discard,
+        );
+        let excepted = resolve_names(
+            &roll.excepted,
+            index,
+            &mut aliases,
+            &mut autocorrector,
+            &mut discard,
+        );
+        let penalised = resolve_names(
+            &roll.penalised,
+            index,
+            &mut aliases,
+            &mut autocorrector,
+            &mut discard,
+        );
+
+        for name in excepted.keys().filter(|n| !credited.contains_key(*n)) {
+            println!(
+                "{} line {}: {} is after `except` but wasn't credited",
+                "Warning:".yellow().bold(),
+                index + 1,
+                name.bold()
+            );
+        }
+
+        for (name, group) in credited.iter().filter(|(n, _)| !excepted.contains_key(*n)) {
+            if penalised.contains_key(name) {
+                println!(
+                    "{} line {}: {} is both credited and after `not`, so is only penalised",
+                    "Warning:".yellow().bold(),
+                    index + 1,
+                    name.bold()
+                );
                 continue;
             }
-        } else if actual_names.len() == 2 && actual_names[0] == "not" {
-            dkp_count
-                .entry(actual_names[1].clone())
-                .and_modify(|p| *p -= points)
-                .or_insert(points);
 
-            continue;
+            let reason = Reason::Kill {
+                credit: credit.clone(),
+                group: group.clone(),
+            };
+            ledger.record(name, points, time, reason);
         }
 
-        let cleaned_names: HashSet<String> =
-            HashSet::from_iter(actual_names.into_iter().filter(|n| n.as_str() != "not"));
-        for name in cleaned_names {
-            dkp_count
-                .entry(name)
-                .and_modify(|p| *p += points)
-                .or_insert(points);
+        for (name, group) in penalised.iter() {
+            let reason = Reason::Not {
+                credit: credit.clone(),
+                group: group.clone(),
+            };
+            ledger.record(name, -points, time, reason);
         }
     }
 
-    let mut dkp_count: Vec<(String, i32)> = dkp_count.into_iter().collect();
+    for adjustment in adjustments {
+        let key = normalise(&adjustment.name.split_whitespace().collect::<String>());
+        let place = match adjustment.line {
+            Some(line) => format!("Line {}", line + 1),
+            None => format!("{} ({})", adjustments::ADJUSTMENTS_FILE, adjustment.reason),
+        };
+
+        let names = resolve_name(&key, &place, &mut aliases, &mut autocorrector, &mut discard);
+
+        if names.is_empty() {
+            println!(
+                "{} skipping adjustment of {:+} for {} ({}) as no spreadsheet name was chosen",
+                "Warning:".yellow().bold(),
+                adjustment.points,
+                adjustment.name.bold(),
+                adjustment.reason
+            );
+        }
 
-    dkp_count.sort_by_key(|(n, _)| n.to_lowercase());
+        for name in names {
+            ledger.record(
+                &name,
+                adjustment.points,
+                adjustment.time,
+                Reason::Adjustment {
+                    reason: adjustment.reason.clone(),
+                    line: adjustment.line,
+                },
+            );
+        }
+    }
 
-    dkp_count.retain(|(_, p)| *p > 0);
+    match args.mode {
+        Mode::Batch => {
+            let dkp_counts = ledger.weekly_totals();
 
-    let mut f = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open("output.txt")
-        .expect("Failed to open or create output file");
+            for (week, dkp_count) in dkp_counts.iter() {
+                let week = week.format("%Y-%m-%d");
+                write_output(&format!("output-{week}.txt"), dkp_count, &args);
+            }
 
-    for (n, p) in dkp_count {
-        f.write_all(format!("{}, {}\n", n, p).as_bytes())
-            .expect("Failed to write to output file");
+            write_summary(&dkp_counts, &args);
+        }
+        _ => write_output("output.txt", &ledger.totals(), &args),
+    }
+
+    if args.explain {
+        explain::write_explain("explain.txt", &ledger);
+    }
+
+    if let Some(member) = &args.member {
+        explain::print_member(&ledger, member);
     }
 }
@@ -0,0 +1,284 @@
+use crate::catalog::SCHEMA_VERSION;
+use crate::catalog::{Alias, Modifier, Restriction, Rounding};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::{iter, mem};
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LegacyAlias {
+    Regex { regex: String, replacement: String },
+    Token(HashMap<String, String>),
+}
+
+fn read_legacy_file<T: DeserializeOwned>(path: &str) -> T {
+    let input = File::open(path).unwrap_or_else(|_| panic!("Cannot find {path}"));
+    serde_json::from_reader(BufReader::new(input))
+        .unwrap_or_else(|_| panic!("{path} does not contain valid json"))
+}
+
+fn modifier(key: &str, multiplier: f64, bonus: i32, rounding: Rounding, order: i32) -> Modifier {
+    Modifier {
+        key: key.to_string(),
+        multiplier,
+        bonus,
+        rounding,
+        order,
+        only: None,
+        conflicts: Vec::new(),
+    }
+}
+
+// The modifiers that were built into get_points before they could be configured
+fn default_modifiers() -> Vec<Modifier> {
+    vec![
+        modifier("brucybonus", 1.0, 5, Rounding::Nearest, 1),
+        Modifier {
+            conflicts: vec!["doublepoints".to_string()],
+            ..modifier("double", 2.0, 0, Rounding::Nearest, 2)
+        },
+        modifier("doublepoints", 2.0, 0, Rounding::Nearest, 2),
+        modifier("fail", 0.5, 0, Rounding::Up, 3),
+        Modifier {
+            only: Some(Restriction {
+                prio: true,
+                categories: Vec::new(),
+                bosses: Vec::new(),
+            }),
+            ..modifier("comp", 1.0, 0, Rounding::Nearest, 0)
+        },
+    ]
+}
+
+fn version_1_to_2(raw: &mut Value) {
+    raw["modifiers"] = serde_json::to_value(default_modifiers()).unwrap();
+}
+
+// Moves a tier's star values, which used to sit alongside its level, into their own object
+fn nest_tiers(points: Option<&mut Value>) -> bool {
+    let Some(Value::Array(tiers)) = points else {
+        return false;
+    };
+
+    for tier in tiers.iter_mut().filter_map(Value::as_object_mut) {
+        let level = tier.remove("level");
+        let stars = mem::take(tier);
+
+        if let Some(level) = level {
+            tier.insert("level".to_string(), level);
+        }
+        tier.insert("points".to_string(), Value::Object(stars));
+    }
+
+    true
+}
+
+// Tiers were only used by legacy, whose tokens like "legacy190.6" were matched in code
+fn version_2_to_3(raw: &mut Value) {
+    let Some(bosses) = raw["bosses"].as_array_mut() else {
+        return;
+    };
+
+    for boss in bosses.iter_mut().filter_map(Value::as_object_mut) {
+        let mut tiered = nest_tiers(boss.get_mut("points"));
+
+        if let Some(revisions) = boss.get_mut("revisions").and_then(Value::as_array_mut) {
+            for revision in revisions {
+                tiered |= nest_tiers(revision.get_mut("points"));
+            }
+        }
+
+        if tiered {
+            add_pattern(boss, |key| format!("{key}{{level}}.{{star}}"));
+        }
+    }
+}
+
+// Moves a star map of ring values under `values`, with the 1 to 4 rings that used to be accepted
+fn count_values(points: Option<&mut Value>) -> bool {
+    let Some(points @ Value::Object(_)) = points else {
+        return false;
+    };
+
+    *points = json!({
+        "count": { "min": 1, "max": 4 },
+        "values": mem::take(points),
+    });
+
+    true
+}
+
+// Rings were the only drops counted, with tokens like "rings3x6" matched in code
+fn version_3_to_4(raw: &mut Value) {
+    let Some(bosses) = raw["bosses"].as_array_mut() else {
+        return;
+    };
+
+    for boss in bosses.iter_mut().filter_map(Value::as_object_mut) {
+        let mut counted = count_values(boss.get_mut("points"));
+
+        if let Some(revisions) = boss.get_mut("revisions").and_then(Value::as_array_mut) {
+            for revision in revisions {
+                counted |= count_values(revision.get_mut("points"));
+            }
+        }
+
+        if counted {
+            add_pattern(boss, |key| format!("{key}{{count}}x{{quality}}"));
+        }
+    }
+}
+
+// Numbered spawns like "root3" were matched in code, for root only
+fn version_4_to_5(raw: &mut Value) {
+    let Some(bosses) = raw["bosses"].as_array_mut() else {
+        return;
+    };
+
+    for boss in bosses.iter_mut().filter_map(Value::as_object_mut) {
+        if boss.get("key").and_then(Value::as_str) == Some("root") {
+            add_pattern(boss, |key| format!("/{key}\\d*/"));
+        }
+    }
+}
+
+// Gives a boss the pattern built from its key, unless it already has one
+fn add_pattern(boss: &mut Map<String, Value>, pattern: impl FnOnce(&str) -> String) {
+    if boss.contains_key("pattern") {
+        return;
+    }
+
+    let Some(key) = boss.get("key").and_then(Value::as_str) else {
+        return;
+    };
+    let pattern = Value::from(pattern(key));
+
+    // Rebuilt so the pattern sits next to the boss's name rather than at the end
+    *boss = mem::take(boss)
+        .into_iter()
+        .flat_map(|(field, value)| {
+            let pattern = (field == "category").then(|| ("pattern".to_string(), pattern.clone()));
+            iter::once((field, value)).chain(pattern)
+        })
+        .collect();
+}
+
+/// Brings an older bosses.json up to the current schema version, one version at a time.
+pub fn upgrade(raw: &mut Value, version: u64) -> Result<(), String> {
+    for version in version..u64::from(SCHEMA_VERSION) {
+        match version {
+            1 => version_1_to_2(raw),
+            2 => version_2_to_3(raw),
+            3 => version_3_to_4(raw),
+            4 => version_4_to_5(raw),
+            _ => return Err(format!("cannot upgrade from version {version}")),
+        }
+    }
+
+    raw["version"] = SCHEMA_VERSION.into();
+
+    Ok(())
+}
+
+fn category(key: &str) -> String {
+    static DUNGEON_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^\d+\.\d+$").expect("Invalid dungeon regex"));
+
+    match key {
+        "rings" | "legacy" => key.to_string(),
+        _ if DUNGEON_RE.is_match(key) => "dungeon".to_string(),
+        _ => "boss".to_string(),
+    }
+}
+
+// The longest spelling people use is usually the boss's full name, e.g. "gelebron" for "gele"
+fn display_name(key: &str, aliases: &[Alias]) -> String {
+    let longest = aliases
+        .iter()
+        .filter_map(|a| match a {
+            Alias::Token(token) if token.chars().all(char::is_alphabetic) => Some(token.as_str()),
+            _ => None,
+        })
+        .chain([key])
+        .max_by_key(|a| a.len())
+        .unwrap_or(key);
+
+    let mut chars = longest.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Builds a version 1 catalog from the files used before bosses.json existed: points.json for
+/// values, boss_aliases.json for misspellings and prios.json for bosses that count for comp.
+pub fn from_legacy_files() -> Value {
+    let points: BTreeMap<String, Value> = read_legacy_file("points.json");
+    let renames: Vec<LegacyAlias> = read_legacy_file("boss_aliases.json");
+    let prios: Vec<String> = read_legacy_file("prios.json");
+
+    let mut aliases = HashMap::<String, Vec<Alias>>::new();
+
+    for rename in renames {
+        match rename {
+            LegacyAlias::Token(renames) => {
+                for (original, replacement) in renames {
+                    aliases
+                        .entry(replacement)
+                        .or_default()
+                        .push(Alias::Token(original));
+                }
+            }
+            LegacyAlias::Regex { regex, replacement } => {
+                let Some(key) = points
+                    .keys()
+                    .filter(|k| replacement.starts_with(k.as_str()))
+                    .max_by_key(|k| k.len())
+                else {
+                    println!("Skipping boss alias /{regex}/ as it doesn't rename to a known boss");
+                    continue;
+                };
+
+                aliases
+                    .entry(key.clone())
+                    .or_default()
+                    .push(Alias::Regex { regex, replacement });
+            }
+        }
+    }
+
+    let bosses: Vec<Value> = points
+        .into_iter()
+        .map(|(key, points)| {
+            let aliases = aliases.remove(&key).unwrap_or_default();
+
+            let mut boss = json!({
+                "key": key,
+                "name": display_name(&key, &aliases),
+                "category": category(&key),
+            });
+
+            if !aliases.is_empty() {
+                boss["aliases"] = serde_json::to_value(aliases).unwrap();
+            }
+            boss["points"] = points;
+            if prios.iter().any(|p| key.contains(p.as_str())) {
+                boss["prio"] = true.into();
+            }
+
+            boss
+        })
+        .collect();
+
+    for (key, _) in aliases {
+        println!("Skipping boss aliases for `{key}` as it has no points");
+    }
+
+    json!({ "version": 1, "bosses": bosses })
+}
@@ -0,0 +1,49 @@
+use caseless::default_case_fold_str;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+// Letters from other scripts that are drawn the same as a latin letter
+const CONFUSABLES: [(char, char); 21] = [
+    ('а', 'a'),
+    ('е', 'e'),
+    ('і', 'i'),
+    ('ї', 'i'),
+    ('ј', 'j'),
+    ('к', 'k'),
+    ('м', 'm'),
+    ('н', 'h'),
+    ('о', 'o'),
+    ('р', 'p'),
+    ('с', 'c'),
+    ('т', 't'),
+    ('у', 'y'),
+    ('х', 'x'),
+    ('ѕ', 's'),
+    ('һ', 'h'),
+    ('ԁ', 'd'),
+    ('α', 'a'),
+    ('ο', 'o'),
+    ('ρ', 'p'),
+    ('ν', 'v'),
+];
+
+fn fold_confusable(c: char) -> char {
+    CONFUSABLES
+        .iter()
+        .find(|(confusable, _)| *confusable == c)
+        .map_or(c, |(_, latin)| *latin)
+}
+
+/// Brings names and timer tokens into a single comparable form: NFKC normalised (so fullwidth
+/// and other compatibility characters become their plain forms), case folded, with lookalike
+/// letters from other scripts replaced by their latin equivalent. Symbols outside ASCII, such as
+/// emoji or zero-width spaces, are dropped, but accents that don't combine into a letter are kept.
+pub fn normalise(text: &str) -> String {
+    let folded = default_case_fold_str(&text.nfkc().collect::<String>());
+
+    folded
+        .chars()
+        .map(fold_confusable)
+        .filter(|c| c.is_ascii() || c.is_alphanumeric() || is_combining_mark(*c))
+        .collect()
+}
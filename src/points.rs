@@ -1,181 +1,155 @@
+use crate::catalog::{catalog, pattern_regex, Boss, Modifier, PerCount, Point, Tier};
+use crate::dates::Timestamp;
 use once_cell::sync::Lazy;
-use regex::Regex;
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs::File, io::BufReader};
-
-pub const MODIFIERS: [&str; 5] = ["brucybonus", "double", "doublepoints", "fail", "comp"];
-
-#[derive(Serialize, Deserialize)]
-#[serde(untagged)]
-#[derive(Debug)]
-enum Point {
-    Value(i32),
-    Rings(HashMap<String, i32>),
-    Legacy(Vec<Tier>),
+use regex::{Captures, Regex};
+
+/// What a boss token and its modifiers are worth, and how that was worked out.
+pub struct Score {
+    pub boss: &'static Boss,
+    // The boss token from the line, which for a pattern holds the count, level or star
+    pub token: String,
+    pub modifiers: Vec<&'static Modifier>,
+    pub base: i32,
+    pub points: i32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Tier {
-    #[serde(rename = "5")]
-    point_5: i32,
-    #[serde(rename = "6")]
-    point_6: i32,
-    level: i32,
+pub enum PointsError {
+    UnknownBoss,
+    InvalidModifiers(String),
 }
 
-lazy_static! {
-    static ref POINTS_MAP: HashMap<String, Point> = {
-        let points_input = File::open("points.json").expect("Cannot find points.json");
-        serde_json::from_reader(BufReader::new(points_input))
-            .expect("Cannot load point values from points.json")
-    };
-}
+/// Splits modifiers written after a boss, e.g. "(double)(fail)", into their names.
+pub fn split_modifiers(text: &str) -> Option<Vec<String>> {
+    let mut modifiers = Vec::<String>::new();
+    let mut rest = text;
 
-lazy_static! {
-    pub static ref BOSSES: Vec<String> = POINTS_MAP.keys().map(String::clone).collect();
-}
+    while !rest.is_empty() {
+        let (modifier, remaining) = rest.strip_prefix('(')?.split_once(')')?;
+        modifiers.push(modifier.to_string());
+        rest = remaining;
+    }
 
-lazy_static! {
-    static ref PRIOS: Vec<String> = {
-        let prios_input = File::open("prios.json").expect("Cannot find prios.json");
-        serde_json::from_reader(BufReader::new(prios_input))
-            .expect("Cannot load bosses from prios.json")
-    };
+    Some(modifiers)
 }
 
-trait RemFirstAndLast {
-    fn rem_first_and_last(&self) -> String;
-}
+fn resolve_modifiers(
+    boss: &Boss,
+    modifiers: &[String],
+) -> Result<Vec<&'static Modifier>, PointsError> {
+    let mut resolved = Vec::<&Modifier>::new();
+
+    for key in modifiers {
+        let Some(modifier) = catalog().modifier(key) else {
+            return Err(PointsError::InvalidModifiers(format!(
+                "unknown modifier `{key}`"
+            )));
+        };
+
+        if !modifier.applies_to(boss) {
+            return Err(PointsError::InvalidModifiers(format!(
+                "`{key}` cannot be used on {}",
+                boss.name
+            )));
+        }
 
-impl RemFirstAndLast for String {
-    fn rem_first_and_last(&self) -> String {
-        let mut chars = self.chars();
-        chars.next();
-        chars.next_back();
-        chars.collect()
+        if resolved.iter().any(|m| m.key == modifier.key) {
+            return Err(PointsError::InvalidModifiers(format!(
+                "`{key}` is used more than once"
+            )));
+        }
+
+        if let Some(other) = resolved.iter().find(|m| m.conflicts_with(modifier)) {
+            return Err(PointsError::InvalidModifiers(format!(
+                "`{}` and `{key}` cannot be used together",
+                other.key
+            )));
+        }
+
+        resolved.push(modifier);
     }
+
+    resolved.sort_by_key(|m| m.order);
+
+    Ok(resolved)
 }
 
-pub fn get_points(boss: &str) -> Option<i32> {
-    static RINGS_CAPTURE_RE: Lazy<Regex> = Lazy::new(|| {
-        Regex::new("^rings(?<num>[1-4])x(?<star>[5-6])$").expect("Invalid rings regex")
-    });
+fn per_count_points(per_count: &PerCount, caps: &Captures) -> Result<i32, PointsError> {
+    let count: i32 = caps["count"]
+        .parse()
+        .map_err(|_| PointsError::UnknownBoss)?;
 
-    static RINGS_MATCH_RE: Lazy<Regex> =
-        Lazy::new(|| Regex::new("rings([1-4])x([5-6])").expect("Invalid rings regex"));
+    if count < per_count.count.min || count > per_count.count.max {
+        return Err(PointsError::UnknownBoss);
+    }
 
-    static LEGACY_CAPTURE_RE: Lazy<Regex> = Lazy::new(|| {
-        Regex::new(r"^legacy(?<level>\d+)\.(?<star>[5-6])$").expect("Invalid legacy regex")
-    });
+    // bosses.json is checked so that no value overflows at the highest count
+    match per_count.values.get(&caps["quality"]) {
+        Some(value) => Ok(value * count),
+        None => Err(PointsError::UnknownBoss),
+    }
+}
 
-    static LEGACY_MATCH_RE: Lazy<Regex> =
-        Lazy::new(|| Regex::new(r"legacy(\d+)\.([5-6])").expect("Invalid legacy regex"));
-
-    static ROOT_RE: Lazy<Regex> =
-        Lazy::new(|| Regex::new(r"^root\d*$").expect("Invalid roots regex"));
-
-    static BOSSES_RE: Lazy<Regex> = Lazy::new(|| {
-        Regex::new(&format!(
-            r"^(?<boss>{})(\((?<modifier>{})\))?$",
-            {
-                let mut temp = BOSSES
-                    .iter()
-                    .filter_map(|b| match POINTS_MAP.get(b).unwrap() {
-                        Point::Value(_) => Some(b.replace('.', r"\.")),
-                        _ => None,
-                    })
-                    .collect::<Vec<String>>();
-
-                temp.push(RINGS_MATCH_RE.to_string());
-                temp.push(LEGACY_MATCH_RE.to_string());
-                temp.push(ROOT_RE.to_string().rem_first_and_last());
-                temp.join("|")
-            },
-            { MODIFIERS.join("|") }
-        ))
-        .expect("Failed to create regex")
-    });
+// The points for the highest tier at or below the captured level, or 0 if it is below them all
+fn tiered_points(tiers: &[Tier], caps: &Captures) -> Result<i32, PointsError> {
+    let level: i32 = caps["level"]
+        .parse()
+        .map_err(|_| PointsError::UnknownBoss)?;
+    let star = &caps["star"];
 
-    let caps = BOSSES_RE.captures(boss)?;
-
-    let mut points = 0;
-    let mut double = false;
-    let mut half = false;
-
-    let stripped_boss = &caps["boss"];
-    if let Some(modifier) = &caps.name("modifier") {
-        match modifier.as_str() {
-            "brucybonus" => points += 5,
-            "doublepoints" => double = true,
-            "double" => double = true,
-            "fail" => half = true,
-            "comp" => {
-                let mut is_prio = false;
-                for prio in PRIOS.iter() {
-                    if boss.contains(prio) {
-                        is_prio = true;
-                        break;
-                    }
-                }
-                if !is_prio {
-                    return None;
-                }
-            }
-            _ => (),
-        }
+    if !tiers.iter().any(|t| t.points.contains_key(star)) {
+        return Err(PointsError::UnknownBoss);
     }
 
-    match POINTS_MAP.get(stripped_boss) {
-        Some(point) => match point {
-            Point::Value(val) => points += val,
-            Point::Rings(_) => return None,
-            Point::Legacy(_) => return None,
-        },
-        None => {
-            if let Some(caps) = RINGS_CAPTURE_RE.captures(stripped_boss) {
-                let num: i32 = caps["num"].parse::<i32>().unwrap();
-                let star = &caps["star"];
-
-                if let Point::Rings(ring) = POINTS_MAP
-                    .get("rings")
-                    .expect("Cannot find legacy in points.json")
-                {
-                    points += ring[star] * num;
-                }
-            } else if let Some(caps) = LEGACY_CAPTURE_RE.captures(stripped_boss) {
-                let level: i32 = caps["level"].parse().unwrap();
-                let star = &caps["star"];
-
-                if let Point::Legacy(tiers) = POINTS_MAP
-                    .get("legacy")
-                    .expect("Cannot find legacy in points.json")
-                {
-                    for tier in tiers {
-                        if level >= tier.level {
-                            points += if star == "5" {
-                                tier.point_5
-                            } else {
-                                tier.point_6
-                            };
-                            break;
-                        }
-                    }
-                }
-            } else if ROOT_RE.is_match(stripped_boss) {
-                points += 4
-            } else {
-                return None;
-            }
-        }
+    Ok(tiers
+        .iter()
+        .find(|t| level >= t.level)
+        .and_then(|t| t.points.get(star).copied())
+        .unwrap_or(0))
+}
+
+pub fn get_points(
+    boss: &str,
+    modifiers: &[String],
+    time: Option<Timestamp>,
+) -> Result<Score, PointsError> {
+    static PATTERNS: Lazy<Vec<(&Boss, Regex)>> = Lazy::new(|| {
+        catalog()
+            .bosses
+            .iter()
+            .filter_map(|boss| {
+                let pattern = boss.pattern.as_ref()?;
+                let regex = pattern_regex(pattern).unwrap_or_else(|e| {
+                    panic!("Invalid pattern for {} in bosses.json: {e}", boss.key)
+                });
+                Some((boss, regex))
+            })
+            .collect()
+    });
+
+    // Exact keys take priority, then the first boss whose pattern matches the whole token
+    let (entry, caps) = match catalog().get(boss) {
+        Some(entry) => (entry, None),
+        None => PATTERNS
+            .iter()
+            .find_map(|(entry, regex)| Some((*entry, Some(regex.captures(boss)?))))
+            .ok_or(PointsError::UnknownBoss)?,
     };
 
-    if double {
-        points *= 2
-    }
+    let base = match (entry.points_at(time), &caps) {
+        (Point::Value(val), _) => *val,
+        (Point::PerCount(per_count), Some(caps)) => per_count_points(per_count, caps)?,
+        (Point::Tiered(tiers), Some(caps)) => tiered_points(tiers, caps)?,
+        _ => return Err(PointsError::UnknownBoss),
+    };
 
-    if half {
-        points = (points + 1) / 2;
-    }
+    let modifiers = resolve_modifiers(entry, modifiers)?;
+    let points = modifiers.iter().fold(base, |points, m| m.apply(points));
 
-    Some(points)
+    Ok(Score {
+        boss: entry,
+        token: boss.to_string(),
+        modifiers,
+        base,
+        points,
+    })
 }
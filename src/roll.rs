@@ -0,0 +1,148 @@
+use crate::config::CONFIG;
+use crate::normalise::normalise;
+
+/// Who a line gives points to and takes points from, written as
+/// `<credited names> [except <names>] [not <names>]`. Names after `except` are taken out of the
+/// credited names, and names after `not` lose the points. A group from config.json can be used
+/// anywhere a name can, as `@<group>`, and names with spaces in them can be put in quotes.
+pub struct Roll {
+    pub credited: Vec<Member>,
+    pub excepted: Vec<Member>,
+    pub penalised: Vec<Member>,
+}
+
+pub struct Member {
+    pub name: String,
+    pub group: Option<String>,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum Section {
+    Credited,
+    Excepted,
+    Penalised,
+}
+
+fn expand(token: &str) -> Result<Vec<Member>, String> {
+    let Some(group) = token.strip_prefix('@') else {
+        return Ok(vec![Member {
+            name: token.to_string(),
+            group: None,
+        }]);
+    };
+
+    match CONFIG.group(group) {
+        Some(names) => Ok(names
+            .iter()
+            .map(|name| Member {
+                name: normalise(&name.split_whitespace().collect::<String>()),
+                group: Some(group.to_string()),
+            })
+            .collect()),
+        None => Err(format!("unknown group `{token}`")),
+    }
+}
+
+// Joins the words of each quoted name, marking which tokens were quoted
+fn join_quoted(tokens: &[String]) -> Result<Vec<(String, bool)>, String> {
+    let mut joined = Vec::<(String, bool)>::new();
+    let mut quoted: Option<Vec<&str>> = None;
+
+    for token in tokens {
+        let token = token.as_str();
+
+        match quoted.as_mut() {
+            Some(words) => words.push(token),
+            None => match token.strip_prefix('"') {
+                Some(rest) => quoted = Some(vec![rest]),
+                None => {
+                    joined.push((token.to_string(), false));
+                    continue;
+                }
+            },
+        }
+
+        let words = quoted.as_mut().unwrap();
+        let last = words.last_mut().unwrap();
+        if let Some(word) = last.strip_suffix('"') {
+            *last = word;
+
+            let name = words.join(" ").trim().to_string();
+            if name.is_empty() {
+                return Err("empty quotes".to_string());
+            }
+
+            joined.push((name, true));
+            quoted = None;
+        }
+    }
+
+    match quoted {
+        Some(words) => Err(format!(
+            "missing closing quote after `\"{}`",
+            words.join(" ")
+        )),
+        None => Ok(joined),
+    }
+}
+
+pub fn parse_roll(tokens: &[String]) -> Result<Roll, String> {
+    let mut roll = Roll {
+        credited: Vec::new(),
+        excepted: Vec::new(),
+        penalised: Vec::new(),
+    };
+    let mut section = Section::Credited;
+
+    for (token, quoted) in join_quoted(tokens)? {
+        let next = match token.as_str() {
+            "except" if !quoted => Section::Excepted,
+            "not" if !quoted => Section::Penalised,
+            _ => {
+                let members = if quoted {
+                    vec![Member {
+                        name: token,
+                        group: None,
+                    }]
+                } else {
+                    expand(&token)?
+                };
+                match section {
+                    Section::Credited => roll.credited.extend(members),
+                    Section::Excepted => roll.excepted.extend(members),
+                    Section::Penalised => roll.penalised.extend(members),
+                }
+                continue;
+            }
+        };
+
+        if next == section {
+            return Err(format!("`{token}` is used more than once"));
+        }
+        if next == Section::Excepted && section == Section::Penalised {
+            return Err("`except` must come before `not`".to_string());
+        }
+
+        if section == Section::Excepted && roll.excepted.is_empty() {
+            return Err("nobody after `except`".to_string());
+        }
+
+        section = next;
+    }
+
+    if section == Section::Excepted && roll.excepted.is_empty() {
+        return Err("nobody after `except`".to_string());
+    }
+    if section == Section::Penalised && roll.penalised.is_empty() {
+        return Err("nobody after `not`".to_string());
+    }
+
+    if roll.credited.is_empty() && !roll.excepted.is_empty() {
+        return Err("nobody before `except` to take names from".to_string());
+    }
+    if roll.credited.is_empty() && roll.penalised.is_empty() {
+        return Err("no names".to_string());
+    }
+
+    Ok(roll)
+}
@@ -1,353 +1,439 @@
-use crate::points::get_points;
-use crate::points::BOSSES;
-use crate::points::MODIFIERS;
-use chrono::Days;
-use chrono::{NaiveDate, NaiveDateTime};
+use crate::adjustments::Adjustment;
+use crate::boss_aliases::rewrite_boss;
+use crate::cli::{Args, Mode};
+use crate::config::CONFIG;
+use crate::dates::{get_date, parse_date_prefix, resolve_relative_date, Timestamp};
+use crate::directives::{parse_directive, Directive};
+use crate::normalise::normalise;
+use crate::points::{get_points, split_modifiers, PointsError, Score};
+use crate::roll::{parse_roll, Roll};
+use chrono::{DateTime, NaiveDate, Utc};
 use colored::*;
-use serde_json::from_reader;
-use std::collections::HashMap;
-use std::env;
-use std::fs::copy;
 use std::fs::File;
-use std::io::IsTerminal;
-use std::io::Read;
+use std::fs::{copy, metadata};
 use std::io::Write;
 use std::io::{BufRead, BufReader};
-use std::process;
 
-fn pre_process_lines(timers: BufReader<File>) -> Vec<(usize, String)> {
-    let bosses_input = File::open("boss_aliases.json").expect("Cannot find boss_aliases.json");
-    let bosses = BufReader::new(bosses_input);
+pub fn tidy_line(line: &str) -> String {
+    // Comments and directives are kept as written, so reasons read the way the officer wrote them
+    if parse_directive(line.trim()).is_some() {
+        return line.trim().to_string();
+    }
 
-    let bosses_renames: Vec<HashMap<String, String>> =
-        from_reader(bosses).expect("boss_aliases.json does not contain valid json");
-    let bosses_renames: Vec<(String, String)> = bosses_renames
-        .into_iter()
-        .flat_map(|b| b.into_iter())
-        .collect();
+    line.split_whitespace()
+        .map(normalise)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
 
-    // Tidy lines
-    let lines: Vec<(usize, String)> = timers
-        .lines()
-        .map(|l| {
-            l.expect("Line not read")
-                .split_whitespace()
-                .map(|x| {
-                    x.chars()
-                        .filter(|y| y.is_ascii())
-                        .collect::<String>()
-                        .to_lowercase()
-                })
-                .collect::<Vec<String>>()
-                .join(" ")
-        })
-        .filter(|l| !l.is_empty())
-        .enumerate()
-        .collect();
+// Replaces a boss alias or misspelling at the start of the line
+pub fn rewrite_line(index: usize, line: String, args: &Args, today: NaiveDate) -> String {
+    if parse_directive(&line).is_some() {
+        return line;
+    }
 
-    // for c in lines[0].1.chars() {
-    //     println!("{c}")
-    // }
+    let offset = parse_date_prefix(&line, today).map_or(0, |(_, offset)| offset);
+    let (date, payload) = line.split_at(offset);
 
-    // println!("{}", lines[0].1);
-    // process::exit(1);
+    let (boss, rest) = payload.split_at(payload.find(' ').unwrap_or(payload.len()));
 
-    // Replace boss aliases/misspellings
-    let lines: Vec<(usize, String)> = lines
-        .into_iter()
-        .map(|(i, line)| {
-            (
-                i,
-                bosses_renames
-                    .iter()
-                    .fold(line, |acc, (original, replacement)| {
-                        acc.replace(original, replacement)
-                    }),
-            )
-        })
-        .collect();
+    match rewrite_boss(boss) {
+        Some(replacement) => {
+            if args.dry_run_aliases {
+                println!("Line {}: {} -> {}", index + 1, boss, replacement.bold());
+            }
 
-    let args: Vec<String> = env::args().collect();
+            format!("{date}{replacement}{rest}")
+        }
+        None => line,
+    }
+}
 
-    let program_name = match env::consts::OS {
-        "windows" => r".\dkp-automator.exe",
-        _ => r"./dkp-automator",
-    };
+// Tidies each line of timers.txt and replaces boss aliases, keeping their line numbers in the file
+pub fn read_lines(args: &Args, today: NaiveDate) -> Vec<Line> {
+    let timers_input = File::open("timers.txt").expect("Cannot find timers.txt");
 
-    let usage_string = format!("Usage:
-
-{}       Calculates dkp for all lines in timers.txt
-{}   Calculates dkp for a 7-day period from the date given
-                        Start date must be in the format \"day short-month year\" e.g. \"2 Jun 2024\"
-                        You can also include a 24-hour time, e.g. \"2 Jun 2024 19:00\"",
-                        format!("{program_name} all").bold(),
-                        format!("{program_name} <start>").bold()
-            );
-
-    if args.len() != 2 {
-        println!(
-            "
-Incorrect number of arguments.
-
-{usage_string}
-"
-        );
-        process::exit(1);
-    }
+    BufReader::new(timers_input)
+        .lines()
+        .map(|l| tidy_line(&l.expect("Line not read")))
+        .enumerate()
+        .filter(|(_, l)| !l.is_empty())
+        .map(|(i, line)| (i, rewrite_line(i, line, args, today)))
+        .collect()
+}
 
-    let arg = &args[1];
-
-    if arg == "all" {
-        lines.to_vec()
-    } else {
-        let start_date = if let Ok(without_time) = NaiveDate::parse_from_str(arg, "%d %b %Y") {
-            without_time.and_hms_opt(0, 0, 0).unwrap()
-        } else if let Ok(withtime) = NaiveDateTime::parse_from_str(arg, "%d %b %Y %H:%M") {
-            withtime
-        } else {
-            println!(
-                "
-Invalid argument `{arg}`.
-
-{usage_string}
-"
-            );
-            process::exit(1);
-        };
+// Lists every boss rewritten by an alias in bosses.json, for `--dry-run-aliases`
+pub fn list_alias_rewrites(args: &Args) {
+    read_lines(args, reference_date(args));
+}
 
-        let end_date = start_date.checked_add_days(Days::new(7)).unwrap();
+fn pre_process_lines(args: &Args, today: NaiveDate) -> Vec<(usize, String)> {
+    let lines = read_lines(args, today);
 
-        let mut start_index = 0;
-        let mut end_index = 0;
+    // for c in lines[0].1.chars() {
+    //     println!("{c}")
+    // }
 
-        for (index, (_, line)) in lines.iter().enumerate().rev() {
-            let as_date = match get_date(line) {
-                Some(date) => date,
-                None => continue,
-            };
+    // println!("{}", lines[0].1);
+    // process::exit(1);
 
-            if end_index == 0 {
-                if as_date <= end_date {
-                    end_index = index;
+    match args.mode {
+        Mode::All | Mode::Batch => lines.to_vec(),
+        Mode::Range(start_date, end_date) => {
+            let mut start_index = 0;
+            let mut end_index = 0;
+
+            for (index, (_, line)) in lines.iter().enumerate().rev() {
+                let as_date = match get_date(line, today) {
+                    Some(date) => date,
+                    None => continue,
+                };
+
+                if end_index == 0 {
+                    if as_date < end_date {
+                        end_index = index;
+                    }
+                } else if start_date.is_some_and(|start_date| as_date < start_date) {
+                    start_index = index + 1;
+                    break;
                 }
-            } else if as_date < start_date {
-                start_index = index + 1;
-                break;
             }
-        }
 
-        let lines: Vec<Line> = lines[start_index..end_index + 1]
-            .iter()
-            .map(|(a, b)| (a - start_index + 1, b.clone()))
-            .collect();
-
-        let mut t = File::options()
-            .write(true)
-            .truncate(true)
-            .open("timers.txt")
-            .unwrap();
+            let lines: Vec<Line> = lines[start_index..end_index + 1]
+                .iter()
+                .enumerate()
+                .map(|(i, (_, line))| (i, line.clone()))
+                .collect();
+
+            let mut t = File::options()
+                .write(true)
+                .truncate(true)
+                .open("timers.txt")
+                .unwrap();
+
+            for line in lines.iter() {
+                let line = resolve_relative_date(&line.1, today);
+                let _ = t.write_all(format!("{line}\n").as_bytes());
+            }
 
-        for line in lines.iter() {
-            let _ = t.write_all(format!("{}\n", line.1).as_bytes());
+            lines
         }
-
-        lines
     }
 }
 
-type Line = (usize, String);
-
-fn check_dates(lines: &Vec<Line>) -> Vec<usize> {
-    let mut error_lines: Vec<usize> = Vec::new();
+pub type Line = (usize, String);
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum ErrorKind {
+    Date,
+    Boss,
+    At,
+    Modifiers,
+    Directive,
+    Roll,
+    SingleCharacter,
+    General,
+}
 
-    for (index, line) in lines {
-        if get_date(line).is_none() {
-            error_lines.push(*index);
+impl ErrorKind {
+    fn heading(self) -> &'static str {
+        match self {
+            ErrorKind::Date => "Cannot read date in lines:",
+            ErrorKind::Boss => "Cannot read boss in lines:",
+            ErrorKind::At => "Word 'at' in lines:",
+            ErrorKind::Modifiers => "Invalid modifiers in lines:",
+            ErrorKind::Directive => "Invalid directives in lines:",
+            ErrorKind::Roll => "Incorrect use of 'not' or 'except' in lines:",
+            ErrorKind::SingleCharacter => "Single character name in lines:",
+            ErrorKind::General => "Error at lines:",
         }
     }
 
-    error_lines
+    pub fn describe(self) -> &'static str {
+        match self {
+            ErrorKind::Date => "Cannot read date",
+            ErrorKind::Boss => "Cannot read boss",
+            ErrorKind::At => "Word 'at'",
+            ErrorKind::Modifiers => "Invalid modifiers",
+            ErrorKind::Directive => "Invalid directive",
+            ErrorKind::Roll => "Incorrect use of 'not' or 'except'",
+            ErrorKind::SingleCharacter => "Single character name",
+            ErrorKind::General => "Missing boss or names",
+        }
+    }
 }
 
-fn get_date(line: &str) -> Option<NaiveDateTime> {
-    if line.len() < 20 {
-        return None;
-    }
+pub struct LineError {
+    pub index: usize,
+    pub kind: ErrorKind,
+    pub reason: Option<String>,
+}
 
-    let date = &line[..20].trim();
-    let fmt = "%d %b %Y at %H:%M";
+pub struct Kill {
+    pub score: Score,
+    pub roll: Roll,
+    pub index: usize,
+    pub time: Timestamp,
+}
 
-    if let Ok(date) = NaiveDateTime::parse_from_str(date, fmt) {
-        return Some(date);
-    }
+// Takes comments and directives out of the lines, along with any line after an `!ignore-next`
+fn split_directives(
+    lines: Vec<Line>,
+    invalid_directive_lines: &mut Vec<(usize, String)>,
+) -> (Vec<Line>, Vec<(usize, Directive)>) {
+    let mut kept = Vec::<Line>::new();
+    let mut directives = Vec::<(usize, Directive)>::new();
+    let mut ignore_next: Option<usize> = None;
 
-    for l in 22..=24 {
-        if line.len() < l {
-            return None;
+    for (index, line) in lines {
+        match parse_directive(&line) {
+            Some(Ok(Directive::Comment)) => (),
+            _ if ignore_next.take().is_some() => (),
+            Some(Ok(Directive::IgnoreNext)) => ignore_next = Some(index),
+            Some(Ok(directive)) => directives.push((index, directive)),
+            Some(Err(reason)) => invalid_directive_lines.push((index, reason)),
+            None => kept.push((index, line)),
         }
+    }
 
-        let date = &line[..l].trim();
-        let fmt = "%b %d, %Y at %I:%M %p";
-
-        if let Ok(date) = NaiveDateTime::parse_from_str(date, fmt) {
-            return Some(date);
-        }
+    if let Some(index) = ignore_next {
+        invalid_directive_lines.push((index, "there is no line after it to ignore".to_string()));
     }
 
-    None
+    (kept, directives)
 }
 
-fn first_index_of_boss(line: &str, bosses: &Vec<String>) -> usize {
-    let mut min = usize::max_value();
-    for boss in bosses {
-        if let Some(i) = line.find(boss) {
-            if i < min {
-                min = i;
-            }
+fn check_dates(lines: &Vec<Line>, today: NaiveDate) -> Vec<usize> {
+    let mut error_lines: Vec<usize> = Vec::new();
+
+    for (index, line) in lines {
+        if get_date(line, today).is_none() {
+            error_lines.push(*index);
         }
     }
 
-    if min < usize::max_value() {
-        min
-    } else {
-        0
-    }
+    error_lines
 }
 
-pub fn get_valid_lines() -> Option<Vec<(i32, Vec<String>, usize)>> {
-    copy("timers.txt", r"timers-original.txt").expect("Cannot find timers.txt");
+pub fn reference_date(args: &Args) -> NaiveDate {
+    if let Some(date) = args.reference_date {
+        return date;
+    }
 
-    let timers_input = File::open("timers.txt").expect("Cannot find timers.txt");
+    let modified = metadata("timers.txt")
+        .and_then(|m| m.modified())
+        .expect("Cannot read modification time of timers.txt");
 
-    let timers = BufReader::new(timers_input);
+    DateTime::<Utc>::from(modified)
+        .with_timezone(&CONFIG.timezone)
+        .date_naive()
+}
 
-    let lines = pre_process_lines(timers);
+// Checks every line, returning the kills and adjustments read along with any errors found
+pub fn validate(lines: &[Line], today: NaiveDate) -> (Vec<Kill>, Vec<Adjustment>, Vec<LineError>) {
+    let mut invalid_directive_lines = Vec::<(usize, String)>::new();
+    let (lines, directives) = split_directives(lines.to_vec(), &mut invalid_directive_lines);
 
-    let error_date_lines = check_dates(&lines);
+    let error_date_lines = check_dates(&lines, today);
     // process::exit(1);
     let mut error_boss_lines = Vec::<usize>::new();
     let mut error_at_lines = Vec::<usize>::new();
     let mut error_single_character_name_lines = Vec::<usize>::new();
-    let mut incorrect_use_of_not_lines = Vec::<usize>::new();
+    let mut incorrect_roll_lines = Vec::<(usize, String)>::new();
+    let mut invalid_modifier_lines = Vec::<(usize, String)>::new();
     let mut general_error_lines = Vec::<usize>::new();
 
-    let boss_lines: Vec<(usize, Vec<String>)> = lines
+    let boss_lines: Vec<(usize, Option<Timestamp>, Vec<String>)> = lines
         .iter()
         .map(|l| {
+            let (time, payload) = match parse_date_prefix(&l.1, today) {
+                Some((time, offset)) => (Some(time), &l.1[offset..]),
+                None => (None, l.1.as_str()),
+            };
+
             (
                 l.0,
-                l.1[first_index_of_boss(&l.1, &BOSSES)..]
-                    .to_string()
-                    .split_whitespace()
-                    .map(str::to_string)
-                    .collect(),
+                time,
+                payload.split_whitespace().map(str::to_string).collect(),
             )
         })
         .collect();
 
-    let mut formatted_lines = Vec::<(i32, Vec<String>, usize)>::new();
+    // Adjustments take the date of the line before them, or the first line if there isn't one
+    let dated_lines: Vec<(usize, Timestamp)> = boss_lines
+        .iter()
+        .filter_map(|(index, time, _)| Some((*index, (*time)?)))
+        .collect();
 
-    for (index, line) in boss_lines.iter() {
-        let mut full_line = line.clone();
+    let mut adjustments = Vec::<Adjustment>::new();
 
-        let modifier = if full_line.len() < 2 {
-            general_error_lines.push(*index);
+    for (index, directive) in directives {
+        let Directive::Adjust {
+            name,
+            points,
+            reason,
+        } = directive
+        else {
             continue;
-        } else {
-            full_line.remove(1)
         };
 
-        let mut is_valid_modifier = false;
+        let time = dated_lines
+            .iter()
+            .rev()
+            .find(|(i, _)| *i < index)
+            .or(dated_lines.first())
+            .map(|(_, time)| *time);
+
+        match time {
+            Some(time) => adjustments.push(Adjustment {
+                name,
+                points,
+                reason,
+                time,
+                line: Some(index),
+            }),
+            None => invalid_directive_lines.push((
+                index,
+                "there is no dated line to take the date from".to_string(),
+            )),
+        }
+    }
 
-        for test in MODIFIERS {
-            if modifier == format!("({})", test) {
-                is_valid_modifier = true;
-            }
+    let mut formatted_lines = Vec::<Kill>::new();
+
+    for (index, time, line) in boss_lines.iter() {
+        let mut full_line = line.clone();
+
+        if full_line.len() < 2 {
+            general_error_lines.push(*index);
+            continue;
         }
 
-        if is_valid_modifier {
-            full_line[0].push_str(&modifier)
-        } else {
-            full_line.insert(1, modifier)
+        let mut boss = full_line.remove(0);
+        let mut modifiers = boss.split_off(boss.find('(').unwrap_or(boss.len()));
+
+        while full_line.first().is_some_and(|t| t.starts_with('(')) {
+            modifiers.push_str(&full_line.remove(0));
         }
 
-        let boss = full_line.remove(0);
-        if let Some(points) = get_points(&boss) {
-            if full_line.contains(&"at".to_string()) {
-                error_at_lines.push(*index)
-            }
+        let Some(modifiers) = split_modifiers(&modifiers) else {
+            invalid_modifier_lines.push((*index, format!("cannot read `{modifiers}`")));
+            continue;
+        };
 
-            if full_line.contains(&"not".to_string()) {
-                if full_line.len() == 3 {
-                    if full_line[1] != "not" {
-                        incorrect_use_of_not_lines.push(*index);
-                    }
-                } else if full_line.len() == 2 {
-                    if full_line[0] != "not" {
-                        incorrect_use_of_not_lines.push(*index);
+        match get_points(&boss, &modifiers, *time) {
+            Ok(score) => {
+                if full_line.contains(&"at".to_string()) {
+                    error_at_lines.push(*index)
+                }
+
+                error_single_character_name_lines.extend(
+                    full_line
+                        .iter()
+                        .filter(|n| n.chars().count() == 1)
+                        .map(|_| index),
+                );
+
+                let roll = match parse_roll(&full_line) {
+                    Ok(roll) => roll,
+                    Err(reason) => {
+                        incorrect_roll_lines.push((*index, reason));
+                        continue;
                     }
-                } else {
-                    incorrect_use_of_not_lines.push(*index);
+                };
+
+                if let Some(time) = time {
+                    formatted_lines.push(Kill {
+                        score,
+                        roll,
+                        index: *index,
+                        time: *time,
+                    });
                 }
             }
-
-            error_single_character_name_lines
-                .extend(full_line.iter().filter(|n| n.len() == 1).map(|_| index));
-
-            formatted_lines.push((points, full_line, *index));
-        } else {
-            error_boss_lines.push(*index)
+            Err(PointsError::UnknownBoss) => error_boss_lines.push(*index),
+            Err(PointsError::InvalidModifiers(reason)) => {
+                invalid_modifier_lines.push((*index, reason))
+            }
         }
     }
 
-    let mut ready = true;
+    let mut errors = Vec::<LineError>::new();
+    let mut add = |kind: ErrorKind, lines: Vec<(usize, Option<String>)>| {
+        errors.extend(lines.into_iter().map(|(index, reason)| LineError {
+            index,
+            kind,
+            reason,
+        }))
+    };
+    let without_reason = |lines: Vec<usize>| lines.into_iter().map(|i| (i, None)).collect();
+    let with_reason = |lines: Vec<(usize, String)>| {
+        lines
+            .into_iter()
+            .map(|(i, reason)| (i, Some(reason)))
+            .collect()
+    };
 
-    if !error_date_lines.is_empty() {
-        ready = false;
-        println!("Cannot read date in lines:");
-        for line in error_date_lines {
-            println!("{}", line + 1);
-        }
-    }
-    if !error_boss_lines.is_empty() {
-        ready = false;
-        println!("Cannot read boss in lines:");
-        for line in error_boss_lines {
-            println!("{}", line + 1);
-        }
-    }
-    if !error_at_lines.is_empty() {
-        ready = false;
-        println!("Word 'at' in lines:");
-        for line in error_at_lines {
-            println!("{}", line + 1);
-        }
-    }
-    if !incorrect_use_of_not_lines.is_empty() {
-        ready = false;
-        println!("Incorrect use of 'not' in lines:");
-        for line in incorrect_use_of_not_lines {
-            println!("{}", line + 1);
-        }
-    }
-    if !error_single_character_name_lines.is_empty() {
-        ready = false;
-        println!("Single character name in lines:");
-        for line in error_single_character_name_lines {
-            println!("{}", line + 1);
+    add(ErrorKind::Date, without_reason(error_date_lines));
+    add(ErrorKind::Boss, without_reason(error_boss_lines));
+    add(ErrorKind::At, without_reason(error_at_lines));
+    add(ErrorKind::Modifiers, with_reason(invalid_modifier_lines));
+    add(ErrorKind::Directive, with_reason(invalid_directive_lines));
+    add(ErrorKind::Roll, with_reason(incorrect_roll_lines));
+    add(
+        ErrorKind::SingleCharacter,
+        without_reason(error_single_character_name_lines),
+    );
+    add(ErrorKind::General, without_reason(general_error_lines));
+
+    (formatted_lines, adjustments, errors)
+}
+
+// Prints the errors grouped by kind, in the order they were found
+pub fn print_errors(errors: &[LineError]) {
+    let mut errors: Vec<&LineError> = errors.iter().collect();
+    errors.sort_by_key(|e| e.kind);
+
+    let mut kind = None;
+
+    for error in errors {
+        if kind != Some(error.kind) {
+            println!("{}", error.kind.heading());
+            kind = Some(error.kind);
         }
-    }
-    if !general_error_lines.is_empty() {
-        ready = false;
-        println!("Error at lines:");
-        for line in general_error_lines {
-            println!("{}", line + 1);
+
+        match &error.reason {
+            Some(reason) => println!("{} ({})", error.index + 1, reason),
+            None => println!("{}", error.index + 1),
         }
     }
+}
+
+// The lines of a timers.txt that didn't pass validation, kept so they can be fixed up
+pub struct Invalid {
+    pub lines: Vec<Line>,
+    pub errors: Vec<LineError>,
+    pub today: NaiveDate,
+}
 
-    if ready {
-        Some(formatted_lines)
-    } else {
-        None
+pub fn get_valid_lines(args: &Args) -> Result<(Vec<Kill>, Vec<Adjustment>), Invalid> {
+    let today = reference_date(args);
+
+    copy("timers.txt", r"timers-original.txt").expect("Cannot find timers.txt");
+
+    let lines = pre_process_lines(args, today);
+
+    let (kills, adjustments, errors) = validate(&lines, today);
+
+    if errors.is_empty() {
+        return Ok((kills, adjustments));
     }
+
+    print_errors(&errors);
+
+    Err(Invalid {
+        lines,
+        errors,
+        today,
+    })
 }